@@ -0,0 +1,33 @@
+//! Compares longest_of_str against the naive iter().max_by_key, run it with 'cargo bench'.
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+// showcase is a binary, so just like the fuzz target we pull in the modules directly.
+// lifetime_ownership uses crate::match_result, so that one has to come along too.
+// clippy checks benches with cfg(test) but without the test harness, so the #[test]
+// functions are dropped and the use super::* in the test modules is left unused.
+#[allow(dead_code, unused_imports)]
+#[path = "../src/match_result.rs"]
+mod match_result;
+
+#[allow(dead_code, unused_imports)]
+#[path = "../src/lifetime_ownership.rs"]
+mod lifetime_ownership;
+
+use lifetime_ownership::longest_of_str;
+
+fn naive<'a>(items: &[&'a str]) -> Option<&'a str> {
+    items.iter().copied().max_by_key(|s| s.len())
+}
+
+fn bench_longest(c: &mut Criterion) {
+    let owned: Vec<String> = (0..100_000).map(|i| "x".repeat(i * 7919 % 64)).collect();
+    let items: Vec<&str> = owned.iter().map(String::as_str).collect();
+
+    c.bench_function("longest_of_str", |b| {
+        b.iter(|| longest_of_str(black_box(&items)))
+    });
+    c.bench_function("naive max_by_key", |b| b.iter(|| naive(black_box(&items))));
+}
+
+criterion_group!(benches, bench_longest);
+criterion_main!(benches);
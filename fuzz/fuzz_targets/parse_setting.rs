@@ -0,0 +1,17 @@
+//! Feeds random bytes into parse_setting, run it with 'cargo fuzz run parse_setting'.
+//! Whatever we throw at it we should get an Ok or an Err back, never a panic.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+// showcase is a binary, so we pull in the module directly instead of depending on a lib
+#[allow(dead_code, unexpected_cfgs)]
+#[path = "../../src/match_result.rs"]
+mod match_result;
+
+fuzz_target!(|data: &[u8]| {
+    // get_conf_val reads the file with read_to_string, which already rejects
+    // anything that's not UTF-8, so parse_setting only ever sees valid strings
+    if let Ok(content) = std::str::from_utf8(data) {
+        let _ = match_result::parse_setting(content);
+    }
+});
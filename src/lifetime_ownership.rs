@@ -1,7 +1,8 @@
-use serde::{Deserialize, Serialize};
+//! Subjects: Ownership, Lifetime
+//! we also started using an external crate named Serde for Serialize/Deserialize
 
-/// Subjects: Ownership, Lifetime
-/// we also started using an external crate named Serde for Serialize/Deserialize
+use crate::match_result::MyError;
+use serde::{Deserialize, Serialize};
 
 /// A simple Data struct
 ///
@@ -20,9 +21,302 @@ use serde::{Deserialize, Serialize};
 /// we also add Debug which let's is print the value nicely and the Eq/partialEq let's us compare
 /// using ==
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
-struct Data<'a> {
+pub struct Data<'a> {
     name: &'a str, // Stack -> 'a to specify who long it needs to live
     email: String, // Heap -> Owned
+    // Not everyone has a phone. When it's None we leave it out of the JSON entirely
+    // instead of writing "phone":null, and when it's missing in the input serde gives us None.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    phone: Option<String>,
+}
+
+/// The owned counterpart of Data.
+///
+/// Data borrows its name, so it can never outlive the &str it borrowed from.
+/// OwnedData has no lifetime at all, both fields are Strings on the heap,
+/// which means we can keep it around for as long as we like.
+/// The price is an allocation (a copy) of the name.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct OwnedData {
+    name: String,
+    email: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    phone: Option<String>,
+}
+/// Serde attributes let us control how fields are named in the JSON.
+/// Rust wants snake_case but JavaScript land usually wants camelCase,
+/// with rename_all we keep both happy: full_name is written as "fullName".
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct DataCamel<'a> {
+    full_name: &'a str,
+    email: String,
+}
+/// A String that is guaranteed not to be empty.
+///
+/// This is the newtype pattern, we wrap a String in a struct of our own. The field is private,
+/// so the only way to get one is through try_new, which checks the invariant. Once we have
+/// a NonEmptyString we never have to check it again, the type itself is the proof.
+#[derive(Debug, Eq, PartialEq)]
+pub struct NonEmptyString(String);
+
+impl NonEmptyString {
+    pub fn try_new(s: impl Into<String>) -> Result<NonEmptyString, MyError> {
+        let s = s.into();
+        if s.is_empty() {
+            return Err(MyError::with_code("validation", "string must not be empty"));
+        }
+        Ok(NonEmptyString(s))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+/// Parses "name,email" into an OwnedData, eg "Kalle,kalle@balle.se".parse::<OwnedData>()
+///
+/// Why not Data? FromStr::from_str(s: &str) has no lifetime tying s to the returned value,
+/// so whatever we return can't borrow from s. Data borrows its name, the best we could do
+/// is a Data<'static>, which would need a name that lives forever. OwnedData owns everything,
+/// so it's the natural fit.
+impl std::str::FromStr for OwnedData {
+    type Err = MyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, email) = s
+            .split_once(',')
+            .ok_or_else(|| MyError::with_code("parse", "expected \"name,email\""))?;
+        // split_once only splits on the first ',', anything after a second one would end up in email
+        if email.contains(',') {
+            return Err(MyError::with_code(
+                "parse",
+                "expected \"name,email\", found more than one ','",
+            ));
+        }
+        let (name, email) = (name.trim(), email.trim());
+        if name.is_empty() || email.is_empty() {
+            return Err(MyError::with_code(
+                "parse",
+                "name and email must not be empty",
+            ));
+        }
+
+        Ok(OwnedData {
+            name: name.to_string(),
+            email: email.to_string(),
+            phone: None,
+        })
+    }
+}
+/// A view of a Data for when it's written out somewhere we don't want full emails, eg logs.
+/// It borrows everything from the Data, the Data itself keeps the real email.
+///
+/// serialize_with tells serde to use our own function for that one field
+/// instead of the Serialize implementation of its type.
+#[derive(Serialize)]
+pub struct RedactedData<'a> {
+    name: &'a str,
+    #[serde(serialize_with = "mask_email")]
+    email: &'a str,
+}
+
+/// Writes "kalle@balle.se" as "k***@balle.se". Without an @ there's nothing
+/// we can safely show, so it's all stars.
+///
+/// serde hands us a reference to the field, which is a &str, hence the &&str.
+fn mask_email<S: serde::Serializer>(email: &&str, serializer: S) -> Result<S::Ok, S::Error> {
+    let masked = match email.split_once('@') {
+        Some((local, domain)) => {
+            // chars().next(), not local[..1], the first letter could be more than one byte
+            let first: String = local.chars().next().into_iter().collect();
+            format!("{}***@{}", first, domain)
+        }
+        None => "***".to_string(),
+    };
+    serializer.serialize_str(&masked)
+}
+
+impl<'a> Data<'a> {
+    /// The derived PartialEq is strict, "A@B.SE" and "a@b.se" are not the same.
+    /// Emails are usually treated as case-insensitive though, so here's an explicit opt-in
+    /// that compares the names and phones exactly and the emails using eq_ignore_ascii_case.
+    pub fn eq_ignore_email_case(&self, other: &Data) -> bool {
+        self.name == other.name
+            && self.email.eq_ignore_ascii_case(&other.email)
+            && self.phone == other.phone
+    }
+
+    /// Breaks the borrow by copying the name into a String of its own.
+    /// The email is already owned so it's simply moved over.
+    ///
+    /// We take self by value, like Cow::into_owned does, 'into' tells the reader
+    /// that the Data is consumed and that's how we can move the email without cloning it.
+    pub fn into_owned(self) -> OwnedData {
+        OwnedData {
+            name: self.name.to_string(),
+            email: self.email,
+            phone: self.phone,
+        }
+    }
+
+    /// Instead of changing the Data in place we hand back an updated copy,
+    /// the original is left untouched.
+    ///
+    /// The name is only a reference, so copying it is cheap and the copy borrows from
+    /// the same place as the original, that's why we return Data<'a> and not some new lifetime.
+    pub fn with_email(&self, email: impl Into<String>) -> Data<'a> {
+        Data {
+            name: self.name,
+            email: email.into(),
+            phone: self.phone.clone(),
+        }
+    }
+
+    /// Looks up a field by its name, handy for templates where the field is only known at runtime.
+    /// Rust has no reflection, so we simply match on the key ourselves.
+    /// An unknown key, or a phone we don't have, gives us None.
+    pub fn field(&self, key: &str) -> Option<String> {
+        match key {
+            "name" => Some(self.name.to_string()),
+            "email" => Some(self.email.clone()),
+            "phone" => self.phone.clone(),
+            _ => None,
+        }
+    }
+
+    /// The names of the fields, in the order they're declared (and serialized) in.
+    /// Tooling can loop over them instead of hard coding them, eg to write a CSV header
+    /// or to look every field up with field(). Keep it in sync when adding a field.
+    pub const FIELDS: &'static [&'static str] = &["name", "email", "phone"];
+
+    /// FIELDS as a function, for where a fn is wanted rather than a value
+    pub fn field_names() -> &'static [&'static str] {
+        Self::FIELDS
+    }
+
+    /// Builds a Data whose name we know isn't empty, the NonEmptyString already checked it.
+    /// The Data borrows the name from the NonEmptyString, so that has to outlive it.
+    pub fn with_valid_name(name: &'a NonEmptyString, email: impl Into<String>) -> Data<'a> {
+        Data {
+            name: name.as_str(),
+            email: email.into(),
+            phone: None,
+        }
+    }
+
+    /// Cleans up the phone number into the international format, eg "+46 70-123 45 67"
+    /// becomes "+46701234567".
+    ///
+    /// People write numbers with spaces and dashes, we strip those. What's left has to be
+    /// a '+' and a country code followed by digits, a country code never starts with 0.
+    /// A local number like "070-123 45 67" has no country code, so that's an Err.
+    pub fn normalized_phone(&self) -> Result<String, MyError> {
+        let phone = self
+            .phone
+            .as_deref()
+            .ok_or_else(|| MyError::with_code("validation", "no phone number"))?;
+        let cleaned: String = phone.chars().filter(|c| *c != ' ' && *c != '-').collect();
+
+        let digits = cleaned.strip_prefix('+').ok_or_else(|| {
+            MyError::with_code(
+                "validation",
+                "phone number must start with + and a country code",
+            )
+        })?;
+        if digits.is_empty()
+            || digits.starts_with('0')
+            || !digits.chars().all(|c| c.is_ascii_digit())
+        {
+            return Err(MyError::with_code(
+                "validation",
+                format!("invalid phone number: {}", phone),
+            ));
+        }
+
+        Ok(cleaned)
+    }
+
+    /// Another opt-in softer equality, this time for user input where " Kalle " and "Kalle"
+    /// should count as the same. Leading and trailing whitespace is trimmed from names,
+    /// emails and phones before comparing, everything else has to match exactly.
+    pub fn eq_trimmed(&self, other: &Data) -> bool {
+        self.name.trim() == other.name.trim()
+            && self.email.trim() == other.email.trim()
+            && self.phone.as_deref().map(str::trim) == other.phone.as_deref().map(str::trim)
+    }
+
+    /// A RedactedData borrowing from this Data, serialize it to get the masked email
+    pub fn redacted(&self) -> RedactedData<'_> {
+        RedactedData {
+            name: self.name,
+            email: &self.email,
+        }
+    }
+
+    /// A stable pseudonymous id for analytics, the hex SHA-256 of the lowercased email.
+    /// The same person always gets the same id, without the email itself leaving our hands.
+    /// We lowercase first so "Kalle@Balle.se" and "kalle@balle.se" are the same person.
+    ///
+    /// Keep in mind a hash of an email is only a pseudonym, anyone with a list of emails
+    /// can hash them and compare. Only with the "sha2" feature.
+    #[cfg(feature = "sha2")]
+    pub fn email_hash(&self) -> String {
+        use sha2::{Digest, Sha256};
+        use std::fmt::Write;
+
+        let digest = Sha256::digest(self.email.to_lowercase().as_bytes());
+        digest
+            .iter()
+            .fold(String::with_capacity(64), |mut hex, byte| {
+                // Writing to a String never fails, there's no Err to handle
+                let _ = write!(hex, "{:02x}", byte);
+                hex
+            })
+    }
+
+    /// An OwnedData with every ASCII control character removed, eg newlines and tabs.
+    /// Someone who names themselves "Kalle\nERROR login failed" could otherwise fake a
+    /// line of their own in our logs, that's called log injection.
+    ///
+    /// We take &self and build new Strings. With a character taken out the text isn't one
+    /// piece of the original anymore, so it can't be borrowed, it has to be owned.
+    /// The original Data stays as it was.
+    /// The phone is cleaned too, it ends up in the logs just the same.
+    pub fn sanitized(&self) -> OwnedData {
+        let clean = |s: &str| {
+            s.chars()
+                .filter(|c| !c.is_ascii_control())
+                .collect::<String>()
+        };
+
+        OwnedData {
+            name: clean(self.name),
+            email: clean(&self.email),
+            phone: self.phone.as_deref().map(clean),
+        }
+    }
+}
+
+/// Here we implement IntoIterator, not for Data itself but for a reference to it (&Data).
+/// That means we can write 'for (key, value) in &data' and still have data afterwards,
+/// nothing is moved.
+///
+/// The values are handed out as owned Strings so the items don't need to borrow from data.
+///
+/// We go through Data::FIELDS and field(), so we agree with them on which fields there are,
+/// and a phone we don't have is simply left out. The closure in filter_map has a type we
+/// can't write down in IntoIter, so we collect into a Vec and hand out its iterator.
+impl<'a> IntoIterator for &'a Data<'a> {
+    type Item = (&'static str, String);
+    type IntoIter = std::vec::IntoIter<(&'static str, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Data::FIELDS
+            .iter()
+            .filter_map(|&key| self.field(key).map(|value| (key, value)))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
 }
 
 /// Introducing the specific case of needing lifetime
@@ -56,7 +350,119 @@ struct Data<'a> {
 /// We take 2 references and returns the longest.
 /// Here we'd get "missing lifetime specifier" without this specification.
 /// We have specified that a and b lives for equally as long here.
+///
+/// If we're handed the same reference twice we return right away. std::ptr::eq compares
+/// addresses, not content, so it's a single comparison however long the strings are.
+/// Checking a == b would have to walk both strings, which costs more than comparing lengths.
+///
+/// If a and b are equally long we get b. The rest of the longest family follows the same
+/// tie rule, for a whole slice that means the later of equally long items wins. So their docs
+/// only bring it up when they do something else: argmax_len, longest_value and longest_capped.
+///
+/// With the "tracing" feature every call gets its own span recording the lengths
+/// of a and b, and ret records what we returned. We skip a and b themselves so only their
+/// lengths go into the span, but ret logs the returned string in full, so whatever a
+/// caller passes in can still end up in the logs.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip(a, b), fields(a_len = a.len(), b_len = b.len()), ret)
+)]
 pub fn longest<'a>(a: &'a String, b: &'a String) -> &'a String {
+    if std::ptr::eq(a, b) {
+        return a;
+    }
+
+    longest_by(a, b, String::len)
+}
+
+/// The comparison in longest, with the length left up to the caller.
+/// Whatever len says is the length is what we compare, it returns a plain usize
+/// since a length is what we're after almost every time:
+/// ```rust ignore
+/// let a = "a,b,c";
+/// let b = "abcdefg";
+/// // Count the fields, not the bytes, and a wins with 3 fields against 1
+/// let fields = |s: &str| s.split(',').count();
+/// assert_eq!(longest_by(a, b, fields), "a,b,c");
+/// assert_eq!(longest_by(a, b, str::len), "abcdefg");
+/// ```
+/// showcase is a binary so this example never runs, the test longest_by_custom_len does.
+/// T: ?Sized lets T be an unsized type like str, we only ever handle it through a reference.
+/// A method like String::len can be passed as len directly, that's what longest does.
+/// The tie rule lives here: only an a that is strictly longer wins, otherwise we get b.
+pub fn longest_by<'a, T: ?Sized>(a: &'a T, b: &'a T, len: impl Fn(&T) -> usize) -> &'a T {
+    if len(a) > len(b) {
+        a
+    } else {
+        b
+    }
+}
+
+/// Sometimes we don't want just the longest, we want them all in order.
+///
+/// Here we borrow the slice mutably (&mut) which lets us sort it in place,
+/// no new Vec is allocated. After the call items[0] is the longest.
+/// We could write sort_by(|a, b| b.len().cmp(&a.len())), but sort_by_key
+/// with Reverse says the same thing a bit clearer.
+/// Both are stable, so strings of equal length keep their original order.
+pub fn sort_by_len(items: &mut [String]) {
+    items.sort_by_key(|s| std::cmp::Reverse(s.len()));
+}
+
+/// What is the length of a string? Rust's len() counts bytes, but JavaScript's
+/// "length" counts UTF-16 code units. Most characters are one unit, but characters
+/// outside the basic plane, like emojis, take two (a so called surrogate pair).
+///
+/// Here we compare using encode_utf16().count() so we agree with JavaScript.
+pub fn longest_by_utf16<'a>(a: &'a str, b: &'a str) -> &'a str {
+    if a.encode_utf16().count() > b.encode_utf16().count() {
+        a
+    } else {
+        b
+    }
+}
+
+/// In a terminal what matters is how many columns a string takes up.
+/// Wide characters, like Chinese or Japanese, take two columns each,
+/// so neither bytes nor chars tell us the visible width.
+///
+/// The unicode-width crate knows the width of every character,
+/// run it with 'cargo test --features unicode-width'.
+#[cfg(feature = "unicode-width")]
+pub fn longest_by_width<'a>(a: &'a str, b: &'a str) -> &'a str {
+    use unicode_width::UnicodeWidthStr;
+
+    if a.width() > b.width() {
+        a
+    } else {
+        b
+    }
+}
+
+/// Here we combine the comparison with some validation and our own error type.
+/// An empty string is not a valid candidate, so if either is empty we stop
+/// and return an Err, otherwise we get the longest back just like with longest.
+pub fn try_longest<'a>(a: &'a str, b: &'a str) -> Result<&'a str, MyError> {
+    if a.is_empty() || b.is_empty() {
+        return Err(MyError::with_code(
+            "validation",
+            "cannot compare an empty string",
+        ));
+    }
+
+    if a.len() > b.len() {
+        Ok(a)
+    } else {
+        Ok(b)
+    }
+}
+
+/// The contrast to longest: here we take ownership of both Strings instead of borrowing them.
+///
+/// Since we own the values there are no lifetimes to worry about, we hand back
+/// the longer one by value and the other one is dropped when the function ends.
+/// The caller can't use a or b afterwards, they've been moved into this function.
+pub fn longest_owned(a: String, b: String) -> String {
     if a.len() > b.len() {
         a
     } else {
@@ -64,79 +470,2116 @@ pub fn longest<'a>(a: &'a String, b: &'a String) -> &'a String {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use serde_json;
+/// Finds both the shortest and the longest string in a single pass over the slice,
+/// instead of scanning it once for each.
+///
+/// An empty slice has neither, so we return None. With a single element that element
+/// is both the shortest and the longest. On ties the first one we saw is kept.
+///
+/// Notice that we don't need to write out any lifetimes here. There's only one
+/// reference going in, so the compiler knows the references coming out borrow from it.
+/// This is called lifetime elision. Written out in full it would be:
+/// fn min_max_by_len<'a>(items: &'a [String]) -> Option<(&'a String, &'a String)>
+pub fn min_max_by_len(items: &[String]) -> Option<(&String, &String)> {
+    let mut iter = items.iter();
+    let first = iter.next()?;
 
-    // This is an example where longest can be called.
-    // both input values lives within the same scope
-    #[test]
-    fn longest_works() {
-        let short = "Shortest string".to_string();
-        let long = String::from("Longest string for sure");
-        let res = longest(&short, &long);
-        assert_eq!(res, &long);
+    let mut shortest = first;
+    let mut longest = first;
+    for item in iter {
+        if item.len() < shortest.len() {
+            shortest = item;
+        }
+        if item.len() > longest.len() {
+            longest = item;
+        }
     }
 
-    // here we get the error: borrowed value does not live long enough
-    // This is because long will be deleted after we exit scope
-    // #[test]
-    // fn longest_nope() {
-    //     let short = "Shortest string".to_string();
-    //     let res = {
-    //         let long = String::from("Longest string for sure");
-    //         longest(&short, &long)
-    //     };
-    //     assert_eq!(res, &short);
-    // }
+    Some((shortest, longest))
+}
 
-    // Each time you leave a scope Rust will clean up that memory.
-    // here we are moving the 'v' into a new scope and when it goes away
-    // and the value is removed out of mem
-    // #[test]
-    // fn ownership_value_is_moved() {
-    //     let mut v = Vec::new();
-    //     v.push(5);
-    //     let scope = { v };
-    //     println!("{:?}", v);
-    // }
+/// Renders a Data as a row in an HTML table.
+///
+/// We can never trust what's in name, email or phone. If someone calls themselves
+/// "<script>...</script>" and we put that straight into the page, the browser runs it (XSS).
+/// So we escape &, < and > before they go into the HTML.
+/// Without a phone the last cell is left empty, so every row has the same three columns.
+pub fn data_to_html_row(data: &Data) -> String {
+    format!(
+        "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+        escape_html(data.name),
+        escape_html(&data.email),
+        escape_html(data.phone.as_deref().unwrap_or(""))
+    )
+}
 
-    // Here we are using the '&' which means that we are 'lending' the value
-    // instead of giving it away. This means that we'll still have the value going further
-    #[test]
-    fn ownership_borrow() {
-        let mut v = Vec::new();
-        v.push(5);
+/// Note that & has to go first, otherwise we'd escape the & in "&lt;" again.
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
 
-        let _scope = { &v };
-        println!("{:?}", v);
+/// Cow stands for Clone on Write, it's either Borrowed (a reference) or Owned (a value).
+/// It's handy for functions that usually can hand back a borrow but sometimes
+/// have to come up with a value of their own.
+///
+/// Here we borrow the longest of a and b, and if both are empty we fall back on the placeholder.
+/// All three live for 'a, so every branch can stay Borrowed and nothing is cloned.
+/// Had we built the placeholder inside the function, eg with format!, we'd return Cow::Owned instead.
+pub fn longest_or_placeholder<'a>(
+    a: &'a str,
+    b: &'a str,
+    placeholder: &'a str,
+) -> std::borrow::Cow<'a, str> {
+    if a.is_empty() && b.is_empty() {
+        std::borrow::Cow::Borrowed(placeholder)
+    } else if a.len() > b.len() {
+        std::borrow::Cow::Borrowed(a)
+    } else {
+        std::borrow::Cow::Borrowed(b)
     }
+}
 
-    // Here we simply use the serialize trait that we added to the Data struct using the drive keyword
-    #[test]
-    fn serialize_data() {
-        let input = Data {
-            name: "Kalle",
-            email: "kalle@balle.se".to_string(), // String::from("")
-        };
+/// The longest of a fixed size array, where the size N is part of the type.
+///
+/// This is const generics, N is a value (a usize) instead of a type. Because the compiler knows N
+/// we can check at compile time that there's at least one item, the const block is evaluated
+/// when the function is compiled for a given N. Calling it with a [String; 0] doesn't build,
+/// so there's no need for an Option in the return type.
+///
+/// The comparison itself is done by longest, so ties go to the later item.
+/// Like min_max_by_len there's a single reference going in, so the lifetime is elided.
+pub fn longest_array<const N: usize>(items: &[String; N]) -> &String {
+    const { assert!(N >= 1, "longest_array needs at least one item") };
 
-        let result = serde_json::to_string(&input).unwrap();
-        assert_eq!(
-            result,
-            "{\"name\":\"Kalle\",\"email\":\"kalle@balle.se\"}".to_string()
-        );
+    let mut best = &items[0];
+    for item in &items[1..] {
+        best = longest(best, item);
     }
+    best
+}
 
-    #[test]
-    fn deserialize_data() {
-        let input = "{\"name\":\"Kalle\",\"email\":\"kalle@balle.se\"}".to_string();
-        let expected = Data {
-            name: "Kalle",
-            email: "kalle@balle.se".to_string(), // String::from("")
-        };
+/// Sometimes we don't need to know which one is the longest, only if either is longer than a threshold.
+///
+/// The || operator short-circuits, if a is already longer we return true
+/// without even looking at b.
+pub fn any_longer_than(a: &str, b: &str, threshold: usize) -> bool {
+    a.len() > threshold || b.len() > threshold
+}
 
-        let result: Data = serde_json::from_str(&input).unwrap();
-        assert_eq!(result, expected);
+/// Deserializes a Data that borrows its name straight out of the json input, zero-copy.
+///
+/// The returned Data<'_> borrows from json, the '_ tells us it can't outlive the input.
+/// serde doesn't allocate a new String for name, it points into the input buffer.
+///
+/// That only works when the name in the JSON is written exactly like the value.
+/// With an escape, eg "Kal\"le", the real value isn't in the buffer so there's nothing
+/// to borrow. A &str field has no way to own it, so serde returns an error.
+/// If we wanted serde to fall back on an owned String there, name would have to be
+/// a Cow<'a, str> marked with #[serde(borrow)].
+pub fn parse_data_borrowed(json: &str) -> Result<Data<'_>, MyError> {
+    let data = serde_json::from_str(json)?;
+    Ok(data)
+}
+
+/// For when the strings arrive one at a time, eg from a stream, and we can't hold them all.
+///
+/// The tracker remembers a reference to the longest string it has seen so far.
+/// Every string we observe has to live for 'a, as long as the tracker itself may hand it out.
+#[derive(Debug, Default)]
+pub struct LongestTracker<'a> {
+    current: Option<&'a str>,
+}
+
+impl<'a> LongestTracker<'a> {
+    pub fn new() -> Self {
+        LongestTracker { current: None }
+    }
+
+    pub fn observe(&mut self, s: &'a str) {
+        match self.current {
+            Some(current) if current.len() > s.len() => {}
+            _ => self.current = Some(s),
+        }
+    }
+
+    /// The longest so far, None until we've observed something
+    pub fn get(&self) -> Option<&'a str> {
+        self.current
+    }
+}
+
+/// A Data wrapper that is equal to another when eq_ignore_email_case says so, which makes it
+/// usable as a HashSet or HashMap key where "A@B.SE" and "a@b.se" are the same person.
+///
+/// Implementing Eq by hand means implementing Hash by hand too. A HashSet only compares
+/// values that land in the same bucket, so the rule is: equal values must have equal hashes.
+/// The derived Hash would hash the email as written and the two cases would (most likely)
+/// land in different buckets, never compared, both kept. So we hash exactly what Eq looks at,
+/// the name as is and the email lowercased. The phone isn't part of either.
+#[derive(Debug)]
+pub struct NormalizedData<'a>(pub Data<'a>);
+
+impl PartialEq for NormalizedData<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.eq_ignore_email_case(&other.0)
+    }
+}
+
+impl Eq for NormalizedData<'_> {}
+
+impl std::hash::Hash for NormalizedData<'_> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.name.hash(state);
+        self.0.email.to_ascii_lowercase().hash(state);
+    }
+}
+
+/// Cleans up an address book by dropping records whose email we've already seen.
+///
+/// We take the Vec by value, the records are moved into the function and the ones we keep
+/// are moved out again, nothing is cloned. The HashSet holds the lowercased emails we've
+/// seen, insert returns false for one that's already there, so only the first occurrence survives.
+/// Like eq_ignore_email_case we ignore ASCII case.
+pub fn dedup_by_email(records: Vec<Data<'_>>) -> Vec<Data<'_>> {
+    let mut seen = std::collections::HashSet::new();
+    records
+        .into_iter()
+        .filter(|record| seen.insert(record.email.to_ascii_lowercase()))
+        .collect()
+}
+
+/// One longest to rule them all. Instead of writing a version per type, we say what
+/// we need from the type in a where clause: it has to be viewable as bytes (AsRef<[u8]>).
+/// String, &str and Vec<u8> all are, so they all work.
+///
+/// The ?Sized bound is needed for str. Generic types are assumed to have a size known
+/// at compile time, str doesn't, and ?Sized lifts that requirement. That's fine for
+/// us since we only ever use T behind a reference.
+pub fn longest_generic<'a, T>(a: &'a T, b: &'a T) -> &'a T
+where
+    T: AsRef<[u8]> + ?Sized,
+{
+    if a.as_ref().len() > b.as_ref().len() {
+        a
+    } else {
+        b
+    }
+}
+
+/// The k longest strings, longest first. Ties keep the order they had in items.
+///
+/// A SmallVec<[&str; 8]> keeps up to 8 items inline, on the stack, and only moves to the heap
+/// when it grows past that. For the usual small k we never allocate at all.
+/// Instead of sorting everything we keep the top k as we go, inserting each item at its place
+/// and dropping whatever falls off the end. Run it with 'cargo test --features smallvec'.
+#[cfg(feature = "smallvec")]
+pub fn top_k_longest(items: &[String], k: usize) -> smallvec::SmallVec<[&str; 8]> {
+    let mut top = smallvec::SmallVec::new();
+
+    for item in items {
+        let pos = top
+            .iter()
+            .position(|t: &&str| t.len() < item.len())
+            .unwrap_or(top.len());
+        if pos < k {
+            top.insert(pos, item.as_str());
+            top.truncate(k);
+        }
+    }
+
+    top
+}
+
+/// Compares only the "meaningful" part of the strings, letters and digits.
+/// Punctuation and whitespace are filtered out in the iterator chain before we count.
+pub fn longest_by_alnum<'a>(a: &'a str, b: &'a str) -> &'a str {
+    let alnum_len = |s: &str| s.chars().filter(|c| c.is_alphanumeric()).count();
+
+    if alnum_len(a) > alnum_len(b) {
+        a
+    } else {
+        b
+    }
+}
+
+/// "é" can be written as one code point (U+00E9) or as an "e" followed by a combining
+/// accent (U+0301). They look the same but the second one is a char longer.
+/// Normalizing to NFC turns the decomposed form into the precomposed one, so we compare
+/// what the reader actually sees. Run it with 'cargo test --features unicode-normalization'.
+///
+/// Here Cow pays off for real: a string that's already NFC is returned Borrowed,
+/// only when normalizing changed something do we hand back an Owned String.
+#[cfg(feature = "unicode-normalization")]
+pub fn longest_normalized<'a>(a: &'a str, b: &'a str) -> std::borrow::Cow<'a, str> {
+    use std::borrow::Cow;
+    use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+    let normalize = |s: &'a str| -> Cow<'a, str> {
+        if is_nfc(s) {
+            Cow::Borrowed(s)
+        } else {
+            Cow::Owned(s.nfc().collect())
+        }
+    };
+
+    let (a, b) = (normalize(a), normalize(b));
+    if a.chars().count() > b.chars().count() {
+        a
+    } else {
+        b
+    }
+}
+
+/// Finds the entry in the map with the longest value, handing back references to both key and value.
+///
+/// A HashMap has no order, iterating it twice may give us the entries in a different order.
+/// So to get the same answer every time a tie goes to the smallest key.
+/// One reference in, so the returned references borrow from the map (elided lifetime).
+pub fn longest_value(
+    map: &std::collections::HashMap<String, String>,
+) -> Option<(&String, &String)> {
+    map.iter()
+        .max_by(|(a_key, a), (b_key, b)| a.len().cmp(&b.len()).then_with(|| b_key.cmp(a_key)))
+}
+
+/// The strict sibling of longest for a whole slice. Lenient code would simply skip the empty
+/// strings, here an empty string (or an empty slice) means something went wrong upstream,
+/// so we return an Err instead of quietly giving an answer.
+pub fn longest_strict<'a>(items: &'a [&'a str]) -> Result<&'a str, MyError> {
+    if let Some(pos) = items.iter().position(|s| s.is_empty()) {
+        return Err(MyError::with_code(
+            "validation",
+            format!("empty string at index {}", pos),
+        ));
+    }
+
+    items
+        .iter()
+        .copied()
+        .reduce(|best, s| if best.len() > s.len() { best } else { s })
+        .ok_or_else(|| MyError::with_code("validation", "no strings to compare"))
+}
+
+/// The strategy pattern: render doesn't know how a Data is turned into text,
+/// it asks whatever Formatter it was given. Adding a new output format is a new
+/// implementation of the trait, render itself never changes.
+pub trait Formatter {
+    fn format(&self, data: &Data) -> String;
+}
+
+/// {"name":"Kalle","email":"kalle@balle.se"}
+pub struct JsonFormatter;
+
+impl Formatter for JsonFormatter {
+    fn format(&self, data: &Data) -> String {
+        serde_json::to_string(data).expect("Data only holds strings, it always serializes")
+    }
+}
+
+/// Kalle <kalle@balle.se>, the way mail clients show an address.
+/// A phone goes after a comma, "Kalle <kalle@balle.se>, 070-123 45 67".
+pub struct PlainFormatter;
+
+impl Formatter for PlainFormatter {
+    fn format(&self, data: &Data) -> String {
+        match &data.phone {
+            Some(phone) => format!("{} <{}>, {}", data.name, data.email, phone),
+            None => format!("{} <{}>", data.name, data.email),
+        }
+    }
+}
+
+/// &dyn Formatter is a trait object, which implementation to call is decided at runtime
+/// (dynamic dispatch). With a generic, f: &impl Formatter, the compiler would instead
+/// create one render per formatter type.
+pub fn render(data: &Data, f: &dyn Formatter) -> String {
+    f.format(data)
+}
+
+/// Remembers the answers of earlier comparisons, keyed on where a and b live in memory.
+///
+/// Comparing lengths is already cheap, so this is mostly a lesson in pointer identity.
+/// The key is where a and b start and how long they are, not the content, so:
+/// - Equal strings at different addresses are different keys, we simply miss the cache.
+/// - While the memo holds &'a str nothing it borrows can be freed, so an address can't be
+///   reused for another string during 'a.
+/// - A sub-slice starts at the same address as the whole, that's why the lengths are part
+///   of the key. Without them s[..1] and s[..5] would share an entry, and an answer cached
+///   for one would be handed out for the other.
+#[derive(Debug, Default)]
+pub struct LongestMemo<'a> {
+    cache: std::collections::HashMap<(usize, usize, usize, usize), &'a str>,
+}
+
+impl<'a> LongestMemo<'a> {
+    pub fn new() -> Self {
+        LongestMemo {
+            cache: std::collections::HashMap::new(),
+        }
+    }
+
+    /// Same answer as longest
+    pub fn longest(&mut self, a: &'a str, b: &'a str) -> &'a str {
+        let key = (a.as_ptr() as usize, a.len(), b.as_ptr() as usize, b.len());
+        if let Some(&cached) = self.cache.get(&key) {
+            return cached;
+        }
+
+        let result = if a.len() > b.len() { a } else { b };
+        self.cache.insert(key, result);
+        result
+    }
+
+    /// How many pairs we remember
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+}
+
+/// The longest, together with how much longer it is than the other one.
+/// Handy when lining up text, the delta is how much padding the shorter one needs.
+///
+/// A tuple lets us return two values without making a struct for it.
+/// abs_diff gives the difference without us having to think about which one is bigger,
+/// a plain subtraction of two usize would panic (in debug) if the result went below zero.
+pub fn longest_with_delta<'a>(a: &'a String, b: &'a String) -> (&'a String, usize) {
+    (longest(a, b), a.len().abs_diff(b.len()))
+}
+
+/// Compares what a reader would count as characters, grapheme clusters.
+/// A flag like "🇸🇪" is two chars (two regional indicators) but one grapheme, one thing on screen.
+///
+/// Behind the "icu" feature we let ICU4X do the segmentation. The locale has to be a valid
+/// BCP-47 tag, eg "sv-SE" or "hi". Unicode's grapheme rules are the same for every locale
+/// (it's word and line breaking that get tailored), so for now all valid locales segment alike.
+/// Fallback: if the locale can't be parsed we don't guess, we count chars instead.
+#[cfg(feature = "icu")]
+pub fn longest_by_locale<'a>(a: &'a str, b: &'a str, locale: &str) -> &'a str {
+    let segmenter = locale
+        .parse::<icu_locid::Locale>()
+        .ok()
+        .map(|_| icu_segmenter::GraphemeClusterSegmenter::new());
+    let len = |s: &str| match &segmenter {
+        // segment_str yields the boundaries, including one at the start, n graphemes have n + 1
+        Some(segmenter) => segmenter.segment_str(s).count().saturating_sub(1),
+        None => s.chars().count(),
+    };
+
+    if len(a) > len(b) {
+        a
+    } else {
+        b
+    }
+}
+
+/// A const fn can be evaluated by the compiler, so the result can be baked right into the
+/// binary, eg const LABEL: &str = longest_const("Name", "Full name");
+/// No work at runtime and nothing allocated, the answer is just another string literal.
+///
+/// Only a subset of Rust is allowed in a const fn, str::len is in it, comparing lengths is too.
+/// It can still be called at runtime like any other function.
+pub const fn longest_const(a: &'static str, b: &'static str) -> &'static str {
+    if a.len() > b.len() {
+        a
+    } else {
+        b
+    }
+}
+
+/// Reads a Data out of a (minimal) vCard, the format address books use to share contacts:
+/// ```text
+/// BEGIN:VCARD
+/// VERSION:4.0
+/// FN:Kalle Balle
+/// EMAIL;TYPE=work:kalle@balle.se
+/// END:VCARD
+/// ```
+/// Each line is PROPERTY;PARAMS:VALUE, we look for FN (the full name), EMAIL and TEL,
+/// ignoring any params and the case of the property. The rest is skipped.
+///
+/// The name is borrowed straight out of s, that's why we return Data<'_>.
+/// FN and EMAIL are required, TEL is optional.
+pub fn data_from_vcard(s: &str) -> Result<Data<'_>, MyError> {
+    let mut name = None;
+    let mut email = None;
+    let mut phone = None;
+
+    for line in s.lines() {
+        let (property, value) = match line.split_once(':') {
+            Some(pair) => pair,
+            None => continue,
+        };
+        let property = property.split(';').next().unwrap_or(property);
+
+        if property.eq_ignore_ascii_case("FN") {
+            name = Some(value.trim());
+        } else if property.eq_ignore_ascii_case("EMAIL") {
+            email = Some(value.trim().to_string());
+        } else if property.eq_ignore_ascii_case("TEL") {
+            phone = Some(value.trim().to_string());
+        }
+    }
+
+    Ok(Data {
+        name: name.ok_or_else(|| MyError::with_code("parse", "vCard has no FN"))?,
+        email: email.ok_or_else(|| MyError::with_code("parse", "vCard has no EMAIL"))?,
+        phone,
+    })
+}
+
+/// Turns a Data into JSON, pretty decides if it's for a human or for a machine.
+/// to_string puts everything on one line, to_string_pretty indents it over several lines.
+/// The ? turns a serde_json::Error into our MyError, thanks to the From impl in match_result.
+pub fn data_to_json(data: &Data, pretty: bool) -> Result<String, MyError> {
+    let json = if pretty {
+        serde_json::to_string_pretty(data)?
+    } else {
+        serde_json::to_string(data)?
+    };
+    Ok(json)
+}
+
+/// A comparator we can hand straight to the std iterator methods: iter.max_by(by_len).
+///
+/// Why &&str? max_by doesn't give up its items to the comparator, it lends them out.
+/// Iterating a Vec<&str> with into_iter() (or iter().copied()) gives us &str items,
+/// so the comparator gets &&str. With plain iter() the items are already &&str and
+/// the comparator would get &&&str, which doesn't fit.
+/// Auto-deref lets us call len() through both references as if they weren't there.
+///
+/// max_by returns the last of equally long items, that's the tie rule of longest for free.
+pub fn by_len(a: &&str, b: &&str) -> std::cmp::Ordering {
+    a.len().cmp(&b.len())
+}
+
+/// A guard for input we don't trust, eg strings coming from a request body.
+/// Before doing any work we check both lengths. Returning early like this is called a
+/// guard clause, the happy path below doesn't have to care about too long input.
+/// max is in bytes (len()), a string of exactly max bytes is still fine.
+pub fn longest_bounded<'a>(a: &'a str, b: &'a str, max: usize) -> Result<&'a str, MyError> {
+    if a.len() > max {
+        return Err(MyError::with_code(
+            "validation",
+            format!("a is {} bytes, max is {}", a.len(), max),
+        ));
+    }
+    if b.len() > max {
+        return Err(MyError::with_code(
+            "validation",
+            format!("b is {} bytes, max is {}", b.len(), max),
+        ));
+    }
+
+    Ok(if a.len() > b.len() { a } else { b })
+}
+
+/// data_to_json for many records at once, a slice of Data becomes a JSON array.
+/// serde already knows how to serialize a slice of anything Serialize, we don't need a loop.
+pub fn data_vec_to_json(records: &[Data]) -> Result<String, MyError> {
+    Ok(serde_json::to_string(records)?)
+}
+
+/// And back again. Just like parse_data_borrowed every name borrows from s, so the
+/// whole Vec<Data<'_>> can't outlive the input. The same escape caveat applies,
+/// one escaped name and the whole array is an Err.
+pub fn data_vec_from_json(s: &str) -> Result<Vec<Data<'_>>, MyError> {
+    Ok(serde_json::from_str(s)?)
+}
+
+/// Like longest_by_alnum, but the caller decides what doesn't count. Handy for identifiers
+/// where "user_id" and "user-id" and "userid" really are the same length.
+/// The closure captures ignore, so the filter can look in it for every char.
+/// We count chars, not bytes, the ignored characters don't have to be ASCII.
+pub fn longest_ignoring<'a>(a: &'a str, b: &'a str, ignore: &[char]) -> &'a str {
+    let kept_len = |s: &str| s.chars().filter(|c| !ignore.contains(c)).count();
+
+    if kept_len(a) > kept_len(b) {
+        a
+    } else {
+        b
+    }
+}
+
+/// longest for a (big) slice of strings, tuned for speed.
+///
+/// items.iter().max_by_key(|s| s.len()) gives the same answer, but it has to carry the
+/// best string along from one step to the next, so every step waits for the one before.
+/// Here we split the work in two passes instead:
+/// - First the max of all the lengths. A plain max over numbers has no such chain,
+///   that's the kind of tight loop the compiler can unroll and turn into SIMD instructions.
+/// - Then we walk from the back until we find a string of that length. That's the
+///   last of the longest, the same one max_by_key gives us.
+///
+/// In the worst case the second pass walks the whole slice again, but it's a cheap compare.
+/// benches/longest_of_str.rs compares the two, run it on your machine before trusting us.
+pub fn longest_of_str<'a>(items: &'a [&'a str]) -> Option<&'a str> {
+    let max = items.iter().map(|s| s.len()).max()?;
+    items.iter().rev().find(|s| s.len() == max).copied()
+}
+
+/// The same Data as data_to_json, but as MessagePack, a binary format that's like
+/// JSON but more compact: numbers and lengths are written as bytes instead of text.
+/// serde makes switching formats easy, Data doesn't change at all.
+///
+/// rmp-serde can write a struct as a plain list of values (to_vec) or with the field
+/// names (to_vec_named). We use the named form, phone is left out when it's None and
+/// without names a reader couldn't tell which value is missing.
+/// Only with the "rmp-serde" feature.
+#[cfg(feature = "rmp-serde")]
+pub fn data_to_msgpack(data: &Data) -> Result<Vec<u8>, MyError> {
+    Ok(rmp_serde::to_vec_named(data)?)
+}
+
+/// And back again, just like parse_data_borrowed the name borrows from bytes.
+#[cfg(feature = "rmp-serde")]
+pub fn data_from_msgpack(bytes: &[u8]) -> Result<Data<'_>, MyError> {
+    Ok(rmp_serde::from_slice(bytes)?)
+}
+
+/// longest for bytes that should be text but might not be, eg read from a socket.
+/// Both are checked with std::str::from_utf8 first, '?' turns a Utf8Error into a MyError.
+/// from_utf8 doesn't copy anything, the &str we return points into the same bytes.
+/// We check both even though we only return one, a bad b is a bug even if a is longer.
+pub fn longest_str_checked<'a>(a: &'a [u8], b: &'a [u8]) -> Result<&'a str, MyError> {
+    let a = std::str::from_utf8(a)?;
+    let b = std::str::from_utf8(b)?;
+
+    Ok(if a.len() > b.len() { a } else { b })
+}
+
+/// longest over a list where "Kalle" and "KALLE" count as the same candidate.
+///
+/// Like dedup_by_email the HashSet remembers the lowercased strings we've seen and only the
+/// first of each survives, we ignore ASCII case there too. Then we pick the longest of what's left.
+///
+/// Duplicates are just as long as the one we kept, so why bother? Because of the tie rule,
+/// without the dedup ["abc", "ABC"] would give us the later "ABC", now we get the first spelling.
+pub fn longest_unique_ci(items: &[String]) -> Option<&String> {
+    let mut seen = std::collections::HashSet::new();
+    items
+        .iter()
+        .filter(|s| seen.insert(s.to_ascii_lowercase()))
+        .max_by_key(|s| s.len())
+}
+
+/// Compares how many characters end up on screen for mixed left-to-right and right-to-left
+/// text, eg Swedish with an Arabic name in it.
+///
+/// Such text often carries invisible formatting characters telling the display which way a
+/// piece should run, eg RLI ... PDI around the Arabic. The bidi algorithm reorders the text
+/// for display and uses those characters up, they never show. Reordering only moves
+/// characters around, it doesn't change how many there are, so what we need to know is
+/// which characters are the formatting ones. unicode-bidi tells us each character's class,
+/// we count the ones that are neither formatting characters nor direction marks.
+///
+/// Only with the "bidi" feature.
+#[cfg(feature = "bidi")]
+pub fn longest_visual<'a>(a: &'a str, b: &'a str) -> &'a str {
+    use unicode_bidi::BidiClass::*;
+
+    let visible_len = |s: &str| {
+        s.chars()
+            .filter(|&c| {
+                let formatting = matches!(
+                    unicode_bidi::bidi_class(c),
+                    LRE | RLE | LRO | RLO | PDF | LRI | RLI | FSI | PDI | BN
+                );
+                // LRM, RLM and ALM are classed like the direction they mark, so we name them
+                !formatting && !matches!(c, '\u{200E}' | '\u{200F}' | '\u{061C}')
+            })
+            .count()
+    };
+
+    if visible_len(a) > visible_len(b) {
+        a
+    } else {
+        b
+    }
+}
+
+/// longest for shared strings. An Arc<str> is a string many owners can hold at once,
+/// cloning it only bumps a reference count, the text itself is never copied.
+///
+/// We take both by value, so they're ours and we return the winner as it is (a move, not
+/// even a clone). The loser is dropped at the end of the function, which counts its owners
+/// down by one. A caller who wants to keep both passes in clones, that's cheap.
+/// No lifetimes here, the Arc keeps the string alive for as long as anyone holds it.
+pub fn longest_arc(a: std::sync::Arc<str>, b: std::sync::Arc<str>) -> std::sync::Arc<str> {
+    if a.len() > b.len() {
+        a
+    } else {
+        b
+    }
+}
+
+/// Reads records from JSON lines, one object per line, eg a big export we don't want
+/// in memory all at once. Nothing is read until the caller asks for the next record,
+/// lines() and map are lazy, so we only ever hold one line.
+///
+/// Why OwnedData and not Data? Every line is read into a String that's thrown away when we
+/// move on to the next, a Data borrowing its name from it would outlive its line.
+/// A bad line is an Err for that line only, the caller decides whether to stop or skip it.
+/// Blank lines are skipped, eg the one a trailing newline can leave behind.
+pub fn data_from_jsonl<R: std::io::BufRead>(
+    reader: R,
+) -> impl Iterator<Item = Result<OwnedData, MyError>> {
+    reader
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+}
+
+/// longest, but we get the loser back too, as (longer, shorter).
+/// Both references in the tuple have the same lifetime 'a, just like the one longest returns.
+///
+/// The tie order follows longest: on a tie b is "longer", so equal strings give us (b, a).
+pub fn split_by_len<'a>(a: &'a String, b: &'a String) -> (&'a String, &'a String) {
+    if a.len() > b.len() {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+/// Compares what's left on the terminal once the styling is gone. "\x1b[1mhi\x1b[0m" is a bold
+/// "hi", 10 bytes but only 2 characters on screen, the rest are ANSI escape sequences.
+///
+/// We skip those by hand rather than pulling in a regex. The common ones (colors, bold,
+/// moving the cursor) are CSI sequences: ESC, '[', any parameters, and a final letter
+/// somewhere in '@'..='~'. An ESC followed by anything else takes that one character with it.
+/// We count chars, not bytes, like the other on-screen comparisons.
+pub fn longest_visible<'a>(a: &'a str, b: &'a str) -> &'a str {
+    let visible_len = |s: &str| {
+        let mut chars = s.chars();
+        let mut len = 0;
+        while let Some(c) = chars.next() {
+            if c != '\x1b' {
+                len += 1;
+                continue;
+            }
+            if chars.next() == Some('[') {
+                // Parameters until the final byte, eg "1;31" in "\x1b[1;31m"
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+        }
+        len
+    };
+
+    if visible_len(a) > visible_len(b) {
+        a
+    } else {
+        b
+    }
+}
+
+/// Runs f on the name of every record and collects what it returns.
+/// A higher order function, f is a function we get as a parameter. The input is borrowed,
+/// f only gets to look at each name as a &str, and what comes out is owned Strings,
+/// so the result doesn't borrow from records and can outlive them.
+pub fn map_names<'a>(records: &'a [Data<'a>], f: impl Fn(&str) -> String) -> Vec<String> {
+    records.iter().map(|record| f(record.name)).collect()
+}
+
+/// longest, but we get an owned copy back in whatever type the caller asks for.
+/// S is picked by the caller, not by us: let s: Box<str> = longest_into("a", "bc");
+/// All we need is a way to make an S out of a &str, that's the From<&str> bound.
+/// String, Box<str>, Rc<str> and Arc<str> all have one.
+///
+/// We can't write the bound as plain From<&str>, a reference in a bound needs a lifetime.
+/// for<'s> reads "for every lifetime 's", S can be made from a &str however long it lives.
+/// That's a higher-ranked trait bound, and it's what lets a and b have any lifetime at all.
+///
+/// Since S is owned the result doesn't borrow from a or b, no lifetime to tie them together.
+pub fn longest_into<S: for<'s> From<&'s str>>(a: &str, b: &str) -> S {
+    S::from(if a.len() > b.len() { a } else { b })
+}
+
+/// Lists what changed between two records, one line per field, eg "email: x -> y".
+/// Fields that are the same aren't mentioned, so identical records give an empty Vec.
+///
+/// We walk Data::FIELDS and compare with field(), so a new field is part of the diff as
+/// soon as it's in those two. A missing phone is written as "-".
+pub fn data_diff(a: &Data, b: &Data) -> Vec<String> {
+    Data::FIELDS
+        .iter()
+        .filter_map(|key| {
+            let (from, to) = (a.field(key), b.field(key));
+            if from == to {
+                return None;
+            }
+            let show = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+            Some(format!("{}: {} -> {}", key, show(from), show(to)))
+        })
+        .collect()
+}
+
+/// longest, but nothing past cap bytes counts. Two strings that are both at least cap long
+/// are a tie, and like in longest b wins the tie.
+///
+/// Asking a &str for its len() is already free, the length is stored next to the pointer,
+/// so the cap isn't about saving time here. It's about keeping the answer bounded: input
+/// from an attacker can't win just by being huge, and the same cap works for metrics that
+/// do have to walk the string, eg a.chars().take(cap).count() never looks past cap chars.
+pub fn longest_capped<'a>(a: &'a str, b: &'a str, cap: usize) -> &'a str {
+    if a.len().min(cap) > b.len().min(cap) {
+        a
+    } else {
+        b
+    }
+}
+
+/// Where the longest is rather than what it is, the index of the longest item.
+/// Handy when items runs alongside other slices, eg names and emails in two Vecs,
+/// the index works for all of them.
+///
+/// Unlike the rest of the longest family the first of equally long items wins here,
+/// the lowest index. max_by would give us the last one, so on equal lengths we say the
+/// lower index is the "greater" one with then_with, just like longest_value does with keys.
+/// impl AsRef<str> lets us pass a slice of String as well as a slice of &str.
+pub fn argmax_len(items: &[impl AsRef<str>]) -> Option<usize> {
+    items
+        .iter()
+        .map(|s| s.as_ref().len())
+        .enumerate()
+        .max_by(|(i, a), (j, b)| a.cmp(b).then_with(|| j.cmp(i)))
+        .map(|(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // This is an example where longest can be called.
+    // both input values lives within the same scope
+    #[test]
+    fn longest_works() {
+        let short = "Shortest string".to_string();
+        let long = String::from("Longest string for sure");
+        let res = longest(&short, &long);
+        assert_eq!(res, &long);
+    }
+
+    // here we get the error: borrowed value does not live long enough
+    // This is because long will be deleted after we exit scope
+    // #[test]
+    // fn longest_nope() {
+    //     let short = "Shortest string".to_string();
+    //     let res = {
+    //         let long = String::from("Longest string for sure");
+    //         longest(&short, &long)
+    //     };
+    //     assert_eq!(res, &short);
+    // }
+
+    // Each time you leave a scope Rust will clean up that memory.
+    // here we are moving the 'v' into a new scope and when it goes away
+    // and the value is removed out of mem
+    // #[test]
+    // fn ownership_value_is_moved() {
+    //     let mut v = Vec::new();
+    //     v.push(5);
+    //     let scope = { v };
+    //     println!("{:?}", v);
+    // }
+
+    // Here we are using the '&' which means that we are 'lending' the value
+    // instead of giving it away. This means that we'll still have the value going further
+    #[test]
+    #[allow(clippy::vec_init_then_push)]
+    fn ownership_borrow() {
+        let mut v = Vec::new();
+        v.push(5);
+
+        let _scope = { &v };
+        println!("{:?}", v);
+    }
+
+    // Here we simply use the serialize trait that we added to the Data struct using the drive keyword
+    #[test]
+    fn serialize_data() {
+        let input = Data {
+            name: "Kalle",
+            email: "kalle@balle.se".to_string(), // String::from("")
+            phone: None,
+        };
+
+        let result = serde_json::to_string(&input).unwrap();
+        assert_eq!(
+            result,
+            "{\"name\":\"Kalle\",\"email\":\"kalle@balle.se\"}".to_string()
+        );
+    }
+
+    #[test]
+    fn deserialize_data() {
+        let input = "{\"name\":\"Kalle\",\"email\":\"kalle@balle.se\"}".to_string();
+        let expected = Data {
+            name: "Kalle",
+            email: "kalle@balle.se".to_string(), // String::from("")
+            phone: None,
+        };
+
+        let result: Data = serde_json::from_str(&input).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    // The strict derived == says no, but our opt-in comparison says yes
+    #[test]
+    fn eq_ignore_email_case_works() {
+        let upper = Data {
+            name: "Kalle",
+            email: "A@B.SE".to_string(),
+            phone: None,
+        };
+        let lower = Data {
+            name: "Kalle",
+            email: "a@b.se".to_string(),
+            phone: None,
+        };
+
+        assert_ne!(upper, lower);
+        assert!(upper.eq_ignore_email_case(&lower));
+    }
+
+    // The name is still compared exactly
+    #[test]
+    fn eq_ignore_email_case_keeps_name_strict() {
+        let a = Data {
+            name: "Kalle",
+            email: "A@B.SE".to_string(),
+            phone: None,
+        };
+        let b = Data {
+            name: "kalle",
+            email: "a@b.se".to_string(),
+            phone: None,
+        };
+
+        assert!(!a.eq_ignore_email_case(&b));
+    }
+
+    // Only the email case is forgiven, a different phone is a different Data
+    #[test]
+    fn eq_ignore_email_case_compares_phone() {
+        let a = Data {
+            name: "Kalle",
+            email: "A@B.SE".to_string(),
+            phone: Some("070-123".to_string()),
+        };
+        let b = Data {
+            name: "Kalle",
+            email: "a@b.se".to_string(),
+            phone: None,
+        };
+
+        assert!(!a.eq_ignore_email_case(&b));
+    }
+
+    // We lend the vector mutably and get it back sorted, longest first.
+    // "bb" and "cc" are equally long and keep their order
+    #[test]
+    fn sort_by_len_descending_and_stable() {
+        let mut items = vec![
+            "a".to_string(),
+            "bb".to_string(),
+            "dddd".to_string(),
+            "cc".to_string(),
+        ];
+        sort_by_len(&mut items);
+        assert_eq!(items, vec!["dddd", "bb", "cc", "a"]);
+    }
+
+    // We iterate over a reference, so input is still ours after the loop
+    #[test]
+    fn iterate_data_fields() {
+        let input = Data {
+            name: "Kalle",
+            email: "kalle@balle.se".to_string(),
+            phone: None,
+        };
+
+        let pairs: Vec<(&str, String)> = (&input).into_iter().collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ("name", "Kalle".to_string()),
+                ("email", "kalle@balle.se".to_string())
+            ]
+        );
+        assert_eq!(input.name, "Kalle");
+    }
+
+    // With a phone there's a third pair, in the same order as Data::FIELDS
+    #[test]
+    fn iterate_data_fields_with_phone() {
+        let input = Data {
+            name: "Kalle",
+            email: "kalle@balle.se".to_string(),
+            phone: Some("+46701234567".to_string()),
+        };
+
+        let keys: Vec<&str> = (&input).into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, Data::FIELDS);
+        assert_eq!(
+            (&input).into_iter().last(),
+            Some(("phone", "+46701234567".to_string()))
+        );
+    }
+
+    // Two emojis are 2 chars but 4 UTF-16 units, so they beat "abc" here
+    // even though "abc" has more chars
+    #[test]
+    fn longest_by_utf16_counts_surrogate_pairs() {
+        let emojis = "😀😀";
+        let abc = "abc";
+        assert_eq!(emojis.chars().count(), 2);
+        assert_eq!(emojis.encode_utf16().count(), 4);
+
+        assert_eq!(longest_by_utf16(emojis, abc), emojis);
+    }
+
+    // The owned copy lives on after the String that Data borrowed from is gone
+    #[test]
+    fn into_owned_outlives_source() {
+        let owned = {
+            let source = String::from("Kalle");
+            let data = Data {
+                name: &source,
+                email: "kalle@balle.se".to_string(),
+                phone: None,
+            };
+            data.into_owned()
+        };
+
+        assert_eq!(
+            owned,
+            OwnedData {
+                name: "Kalle".to_string(),
+                email: "kalle@balle.se".to_string(),
+                phone: None,
+            }
+        );
+    }
+
+    // "日本" is only 2 chars but takes 4 columns, "abc" is 3 chars and 3 columns
+    #[cfg(feature = "unicode-width")]
+    #[test]
+    fn longest_by_width_counts_columns() {
+        let cjk = "日本";
+        let abc = "abc";
+        assert!(cjk.chars().count() < abc.chars().count());
+
+        assert_eq!(longest_by_width(cjk, abc), cjk);
+    }
+
+    #[test]
+    fn try_longest_both_non_empty() {
+        let res = try_longest("short", "longer");
+        assert_eq!(res.unwrap(), "longer");
+    }
+
+    #[test]
+    fn try_longest_one_empty() {
+        let res = try_longest("", "longer");
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "cannot compare an empty string"
+        );
+    }
+
+    #[test]
+    fn try_longest_both_empty() {
+        assert!(try_longest("", "").is_err());
+    }
+
+    // The Rust field is full_name, but the JSON key is fullName
+    #[test]
+    fn serialize_camel_case() {
+        let input = DataCamel {
+            full_name: "Kalle Balle",
+            email: "kalle@balle.se".to_string(),
+        };
+
+        let result = serde_json::to_string(&input).unwrap();
+        assert_eq!(
+            result,
+            "{\"fullName\":\"Kalle Balle\",\"email\":\"kalle@balle.se\"}".to_string()
+        );
+        assert!(!result.contains("full_name"));
+    }
+
+    // Both values are moved in, we get the longer one back and own it
+    #[test]
+    fn longest_owned_works() {
+        let short = "Shortest string".to_string();
+        let long = String::from("Longest string for sure");
+        let res = longest_owned(short, long);
+        assert_eq!(res, "Longest string for sure".to_string());
+    }
+
+    #[test]
+    fn min_max_by_len_empty() {
+        let items: Vec<String> = Vec::new();
+        assert_eq!(min_max_by_len(&items), None);
+    }
+
+    #[test]
+    fn min_max_by_len_single() {
+        let items = vec!["only".to_string()];
+        let (shortest, longest) = min_max_by_len(&items).unwrap();
+        assert_eq!(shortest, "only");
+        assert_eq!(longest, "only");
+    }
+
+    #[test]
+    fn min_max_by_len_many() {
+        let items = vec![
+            "medium".to_string(),
+            "a".to_string(),
+            "the longest".to_string(),
+            "bb".to_string(),
+        ];
+        let (shortest, longest) = min_max_by_len(&items).unwrap();
+        assert_eq!(shortest, "a");
+        assert_eq!(longest, "the longest");
+    }
+
+    // The script tag should come out as harmless text
+    #[test]
+    fn data_to_html_row_escapes() {
+        let input = Data {
+            name: "<script>alert('hi')</script>",
+            email: "kalle&balle@balle.se".to_string(),
+            phone: None,
+        };
+
+        assert_eq!(
+            data_to_html_row(&input),
+            "<tr><td>&lt;script&gt;alert('hi')&lt;/script&gt;</td><td>kalle&amp;balle@balle.se</td><td></td></tr>"
+        );
+    }
+
+    // The phone gets its own cell, and is escaped as well
+    #[test]
+    fn data_to_html_row_with_phone() {
+        let input = Data {
+            name: "Kalle",
+            email: "kalle@balle.se".to_string(),
+            phone: Some("<b>070</b>".to_string()),
+        };
+
+        assert_eq!(
+            data_to_html_row(&input),
+            "<tr><td>Kalle</td><td>kalle@balle.se</td><td>&lt;b&gt;070&lt;/b&gt;</td></tr>"
+        );
+    }
+
+    #[test]
+    fn longest_or_placeholder_normal() {
+        let res = longest_or_placeholder("short", "longer", "n/a");
+        assert_eq!(res, "longer");
+        assert!(matches!(res, std::borrow::Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn longest_or_placeholder_both_empty() {
+        let res = longest_or_placeholder("", "", "n/a");
+        assert_eq!(res, "n/a");
+    }
+
+    #[test]
+    fn with_email_returns_updated_copy() {
+        let original = Data {
+            name: "Kalle",
+            email: "kalle@balle.se".to_string(),
+            phone: None,
+        };
+        let updated = original.with_email("kalle@example.com");
+
+        assert_eq!(original.email, "kalle@balle.se");
+        assert_eq!(updated.email, "kalle@example.com");
+        assert_eq!(updated.name, original.name);
+    }
+
+    // The array size is part of the type, [String; 3]
+    #[test]
+    fn longest_array_works() {
+        let items = ["one".to_string(), "three".to_string(), "two".to_string()];
+        assert_eq!(longest_array(&items), "three");
+    }
+
+    // longest_array(&[]) would not compile since N would be 0
+    #[test]
+    fn longest_array_single() {
+        let items = ["only".to_string()];
+        assert_eq!(longest_array(&items), "only");
+    }
+
+    #[test]
+    fn any_longer_than_neither() {
+        assert!(!any_longer_than("abc", "de", 3));
+    }
+
+    #[test]
+    fn any_longer_than_one() {
+        assert!(any_longer_than("abc", "defgh", 3));
+    }
+
+    #[test]
+    fn any_longer_than_both() {
+        assert!(any_longer_than("abcd", "defgh", 3));
+    }
+
+    // The name should point into the input, proving that nothing was copied
+    #[test]
+    fn parse_data_borrowed_is_zero_copy() {
+        let input = "{\"name\":\"Kalle\",\"email\":\"kalle@balle.se\"}".to_string();
+        let data = parse_data_borrowed(&input).unwrap();
+
+        assert_eq!(data.name, "Kalle");
+        let range = input.as_bytes().as_ptr_range();
+        assert!(range.contains(&data.name.as_ptr()));
+    }
+
+    // An escaped name can't be borrowed, and a &str can't own it either
+    #[test]
+    fn parse_data_borrowed_escaped_name() {
+        let input = "{\"name\":\"Kal\\\"le\",\"email\":\"kalle@balle.se\"}";
+        assert!(parse_data_borrowed(input).is_err());
+    }
+
+    #[test]
+    fn longest_tracker_follows_the_stream() {
+        let mut tracker = LongestTracker::new();
+        assert_eq!(tracker.get(), None);
+
+        for s in ["a", "abc", "ab", "abcde", "xyz"] {
+            tracker.observe(s);
+        }
+        assert_eq!(tracker.get(), Some("abcde"));
+    }
+
+    #[test]
+    fn longest_tracker_tie_goes_to_newest() {
+        let mut tracker = LongestTracker::new();
+        tracker.observe("abc");
+        tracker.observe("xyz");
+        assert_eq!(tracker.get(), Some("xyz"));
+    }
+
+    #[test]
+    fn dedup_by_email_keeps_first() {
+        let records = vec![
+            Data {
+                name: "Kalle",
+                email: "kalle@balle.se".to_string(),
+                phone: None,
+            },
+            Data {
+                name: "Olle",
+                email: "olle@balle.se".to_string(),
+                phone: None,
+            },
+            Data {
+                name: "KALLE",
+                email: "Kalle@Balle.SE".to_string(),
+                phone: None,
+            },
+        ];
+
+        let result = dedup_by_email(records);
+        let names: Vec<&str> = result.iter().map(|d| d.name).collect();
+        assert_eq!(names, vec!["Kalle", "Olle"]);
+    }
+
+    #[test]
+    fn longest_generic_string() {
+        let short = "Shortest string".to_string();
+        let long = String::from("Longest string for sure");
+        assert_eq!(longest_generic(&short, &long), &long);
+    }
+
+    // Here T is str, which is where ?Sized comes in
+    #[test]
+    fn longest_generic_str() {
+        assert_eq!(longest_generic("abc", "ab"), "abc");
+    }
+
+    #[test]
+    fn longest_generic_bytes() {
+        let a: Vec<u8> = vec![1, 2, 3];
+        let b: Vec<u8> = vec![1];
+        assert_eq!(longest_generic(&a, &b), &a);
+    }
+
+    #[test]
+    fn serialize_data_with_phone() {
+        let input = Data {
+            name: "Kalle",
+            email: "kalle@balle.se".to_string(),
+            phone: Some("+46701234567".to_string()),
+        };
+
+        let result = serde_json::to_string(&input).unwrap();
+        assert!(result.contains("\"phone\":\"+46701234567\""));
+    }
+
+    // No phone, no key. Not even "phone":null
+    #[test]
+    fn serialize_data_without_phone() {
+        let input = Data {
+            name: "Kalle",
+            email: "kalle@balle.se".to_string(),
+            phone: None,
+        };
+
+        let result = serde_json::to_string(&input).unwrap();
+        assert!(!result.contains("phone"));
+    }
+
+    #[cfg(feature = "smallvec")]
+    fn top_k_items() -> Vec<String> {
+        vec![
+            "bb".to_string(),
+            "dddd".to_string(),
+            "a".to_string(),
+            "cc".to_string(),
+        ]
+    }
+
+    #[cfg(feature = "smallvec")]
+    #[test]
+    fn top_k_longest_smaller_k() {
+        let items = top_k_items();
+        let top = top_k_longest(&items, 2);
+        assert_eq!(top.as_slice(), &["dddd", "bb"]);
+        assert!(!top.spilled());
+    }
+
+    #[cfg(feature = "smallvec")]
+    #[test]
+    fn top_k_longest_equal_k() {
+        let items = top_k_items();
+        let top = top_k_longest(&items, 4);
+        assert_eq!(top.as_slice(), &["dddd", "bb", "cc", "a"]);
+    }
+
+    #[cfg(feature = "smallvec")]
+    #[test]
+    fn top_k_longest_larger_k() {
+        let items = top_k_items();
+        let top = top_k_longest(&items, 10);
+        assert_eq!(top.as_slice(), &["dddd", "bb", "cc", "a"]);
+    }
+
+    #[test]
+    fn field_by_key() {
+        let input = Data {
+            name: "Kalle",
+            email: "kalle@balle.se".to_string(),
+            phone: None,
+        };
+
+        assert_eq!(input.field("name"), Some("Kalle".to_string()));
+        assert_eq!(input.field("email"), Some("kalle@balle.se".to_string()));
+        assert_eq!(input.field("age"), None);
+    }
+
+    #[test]
+    fn non_empty_string_ok() {
+        let name = NonEmptyString::try_new("Kalle").unwrap();
+        assert_eq!(name.as_str(), "Kalle");
+
+        let data = Data::with_valid_name(&name, "kalle@balle.se");
+        assert_eq!(data.name, "Kalle");
+    }
+
+    #[test]
+    fn non_empty_string_empty() {
+        let e = NonEmptyString::try_new("").unwrap_err();
+        assert_eq!(e.to_string(), "string must not be empty");
+    }
+
+    // "!!!ab!!!" is longer in bytes, but only has 2 letters against 3
+    #[test]
+    fn longest_by_alnum_ignores_punctuation() {
+        let noisy = "!!!ab!!!";
+        let plain = "abc";
+        assert_eq!(longest(&noisy.to_string(), &plain.to_string()), noisy);
+        assert_eq!(longest_by_alnum(noisy, plain), plain);
+    }
+
+    #[test]
+    fn owned_data_from_str() {
+        let data: OwnedData = "Kalle, kalle@balle.se".parse().unwrap();
+        assert_eq!(data.name, "Kalle");
+        assert_eq!(data.email, "kalle@balle.se");
+    }
+
+    #[test]
+    fn owned_data_from_str_malformed() {
+        assert!("Kalle".parse::<OwnedData>().is_err());
+        assert!(",kalle@balle.se".parse::<OwnedData>().is_err());
+        assert!("Kalle,kalle@balle.se,extra".parse::<OwnedData>().is_err());
+    }
+
+    // The decomposed "résumé" is 8 chars, but only 6 once normalized, so "resumes" wins
+    #[cfg(feature = "unicode-normalization")]
+    #[test]
+    fn longest_normalized_decomposed_loses() {
+        let decomposed = "re\u{301}sume\u{301}";
+        let plain = "resumes";
+        assert!(decomposed.chars().count() > plain.chars().count());
+
+        let res = longest_normalized(decomposed, plain);
+        assert_eq!(res, "resumes");
+        assert!(matches!(res, std::borrow::Cow::Borrowed(_)));
+    }
+
+    // When the decomposed string wins we get the precomposed form back, as an owned String
+    #[cfg(feature = "unicode-normalization")]
+    #[test]
+    fn longest_normalized_returns_nfc() {
+        let res = longest_normalized("e\u{301}e\u{301}e\u{301}", "ab");
+        assert_eq!(res, "\u{e9}\u{e9}\u{e9}");
+        assert!(matches!(res, std::borrow::Cow::Owned(_)));
+    }
+
+    #[test]
+    fn normalized_phone_ok() {
+        let input = Data {
+            name: "Kalle",
+            email: "kalle@balle.se".to_string(),
+            phone: Some("+46 70-123 45 67".to_string()),
+        };
+        assert_eq!(input.normalized_phone().unwrap(), "+46701234567");
+    }
+
+    #[test]
+    fn normalized_phone_without_plus() {
+        let input = Data {
+            name: "Kalle",
+            email: "kalle@balle.se".to_string(),
+            phone: Some("070-123 45 67".to_string()),
+        };
+        assert!(input.normalized_phone().is_err());
+    }
+
+    // traced_test collects everything traced during the test so we can look at it
+    #[cfg(feature = "tracing")]
+    #[tracing_test::traced_test]
+    #[test]
+    fn longest_is_traced() {
+        let short = "Shortest string".to_string();
+        let long = String::from("Longest string for sure");
+        longest(&short, &long);
+
+        assert!(logs_contain("longest{a_len=15 b_len=23}"));
+        assert!(logs_contain("return=\"Longest string for sure\""));
+    }
+
+    #[test]
+    fn eq_trimmed_ignores_surrounding_whitespace() {
+        let padded = Data {
+            name: "  Kalle ",
+            email: "kalle@balle.se\t".to_string(),
+            phone: None,
+        };
+        let clean = Data {
+            name: "Kalle",
+            email: "kalle@balle.se".to_string(),
+            phone: None,
+        };
+
+        assert_ne!(padded, clean);
+        assert!(padded.eq_trimmed(&clean));
+    }
+
+    // Same name and email, but a different phone is still a different person
+    #[test]
+    fn eq_trimmed_compares_phone() {
+        let a = Data {
+            name: "Kalle",
+            email: "kalle@balle.se".to_string(),
+            phone: Some(" 070-123 ".to_string()),
+        };
+        let b = Data {
+            name: "Kalle",
+            email: "kalle@balle.se".to_string(),
+            phone: Some("070-999".to_string()),
+        };
+
+        assert!(!a.eq_trimmed(&b));
+        assert!(a.eq_trimmed(&Data {
+            phone: Some("070-123".to_string()),
+            ..b
+        }));
+    }
+
+    #[test]
+    fn longest_value_in_map() {
+        let mut map = std::collections::HashMap::new();
+        map.insert("host".to_string(), "localhost".to_string());
+        map.insert("port".to_string(), "8080".to_string());
+        map.insert("path".to_string(), "/".to_string());
+
+        let (key, value) = longest_value(&map).unwrap();
+        assert_eq!(key, "host");
+        assert_eq!(value, "localhost");
+    }
+
+    #[test]
+    fn longest_value_tie_goes_to_smallest_key() {
+        let mut map = std::collections::HashMap::new();
+        map.insert("b".to_string(), "xx".to_string());
+        map.insert("a".to_string(), "yy".to_string());
+        map.insert("c".to_string(), "zz".to_string());
+
+        assert_eq!(longest_value(&map).unwrap().0, "a");
+        assert_eq!(longest_value(&std::collections::HashMap::new()), None);
+    }
+
+    #[test]
+    fn longest_strict_all_non_empty() {
+        let items = ["a", "abc", "ab"];
+        assert_eq!(longest_strict(&items).unwrap(), "abc");
+    }
+
+    #[test]
+    fn longest_strict_with_empty() {
+        let items = ["a", "", "ab"];
+        let e = longest_strict(&items).unwrap_err();
+        assert_eq!(e.to_string(), "empty string at index 1");
+    }
+
+    // Same Data, two strategies
+    #[test]
+    fn render_with_both_formatters() {
+        let input = Data {
+            name: "Kalle",
+            email: "kalle@balle.se".to_string(),
+            phone: None,
+        };
+
+        assert_eq!(
+            render(&input, &JsonFormatter),
+            "{\"name\":\"Kalle\",\"email\":\"kalle@balle.se\"}"
+        );
+        assert_eq!(render(&input, &PlainFormatter), "Kalle <kalle@balle.se>");
+    }
+
+    #[test]
+    fn plain_formatter_with_phone() {
+        let input = Data {
+            name: "Kalle",
+            email: "kalle@balle.se".to_string(),
+            phone: Some("070-123 45 67".to_string()),
+        };
+
+        assert_eq!(
+            render(&input, &PlainFormatter),
+            "Kalle <kalle@balle.se>, 070-123 45 67"
+        );
+    }
+
+    #[test]
+    fn longest_memo_agrees_with_longest() {
+        let words = ["a", "abc", "ab", "abcd"];
+        let mut memo = LongestMemo::new();
+
+        // Twice over the same pairs, the second round is all cache hits
+        for _ in 0..2 {
+            for a in &words {
+                for b in &words {
+                    let expected = longest(&a.to_string(), &b.to_string()).clone();
+                    assert_eq!(memo.longest(a, b), expected);
+                }
+            }
+        }
+        assert_eq!(memo.len(), words.len() * words.len());
+    }
+
+    // s[..2] and s[..3] start at the same address, the memo must not mix them up
+    #[test]
+    fn longest_memo_sub_slices() {
+        let s = "abcdef";
+        let mut memo = LongestMemo::new();
+        assert!(memo.is_empty());
+        assert_eq!(memo.longest(&s[..3], "x"), "abc");
+        assert_eq!(memo.longest(&s[..2], "x"), "ab");
+
+        // The winner flips between the two calls, same start but not the same length
+        let mut memo = LongestMemo::new();
+        assert_eq!(memo.longest(&s[..1], "xyz"), "xyz");
+        assert_eq!(memo.longest(&s[..5], "xyz"), "abcde");
+        assert_eq!(memo.len(), 2);
+    }
+
+    #[test]
+    fn longest_with_delta_unequal() {
+        let short = "abc".to_string();
+        let long = "abcdef".to_string();
+        assert_eq!(longest_with_delta(&short, &long), (&long, 3));
+        assert_eq!(longest_with_delta(&long, &short), (&long, 3));
+    }
+
+    #[test]
+    fn longest_with_delta_equal() {
+        let a = "abc".to_string();
+        let b = "xyz".to_string();
+        assert_eq!(longest_with_delta(&a, &b), (&b, 0));
+    }
+
+    #[test]
+    fn redacted_masks_email() {
+        let input = Data {
+            name: "Kalle",
+            email: "kalle@balle.se".to_string(),
+            phone: None,
+        };
+
+        let result = serde_json::to_string(&input.redacted()).unwrap();
+        assert_eq!(result, "{\"name\":\"Kalle\",\"email\":\"k***@balle.se\"}");
+        assert_eq!(input.email, "kalle@balle.se");
+    }
+
+    #[test]
+    fn redacted_without_at() {
+        let input = Data {
+            name: "Kalle",
+            email: "not an email".to_string(),
+            phone: None,
+        };
+
+        let result = serde_json::to_string(&input.redacted()).unwrap();
+        assert!(result.contains("\"email\":\"***\""));
+    }
+
+    // Two flags are 4 chars but only 2 graphemes, "abc" is 3 of both
+    #[cfg(feature = "icu")]
+    #[test]
+    fn longest_by_locale_counts_graphemes() {
+        let flags = "🇸🇪🇸🇪";
+        let abc = "abc";
+        assert_eq!(longest_by_locale(flags, abc, "sv-SE"), abc);
+    }
+
+    // A locale we can't parse falls back on counting chars
+    #[cfg(feature = "icu")]
+    #[test]
+    fn longest_by_locale_unknown_falls_back() {
+        let flags = "🇸🇪🇸🇪";
+        let abc = "abc";
+        assert_eq!(longest_by_locale(flags, abc, "not a locale!"), flags);
+    }
+
+    // Evaluated at compile time, LABEL is a plain constant
+    #[test]
+    fn longest_const_in_const_context() {
+        const LABEL: &str = longest_const("Name", "Full name");
+        assert_eq!(LABEL, "Full name");
+    }
+
+    #[test]
+    fn data_from_vcard_ok() {
+        let vcard = "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Kalle Balle\r\nEMAIL;TYPE=work:kalle@balle.se\r\nEND:VCARD\r\n";
+        let data = data_from_vcard(vcard).unwrap();
+        assert_eq!(
+            data,
+            Data {
+                name: "Kalle Balle",
+                email: "kalle@balle.se".to_string(),
+                phone: None,
+            }
+        );
+    }
+
+    #[test]
+    fn data_from_vcard_missing_email() {
+        let vcard = "BEGIN:VCARD\nFN:Kalle Balle\nEND:VCARD\n";
+        let e = data_from_vcard(vcard).unwrap_err();
+        assert_eq!(e.to_string(), "vCard has no EMAIL");
+    }
+
+    // The same reference twice takes the fast path, and we get that reference back
+    #[test]
+    fn longest_same_reference() {
+        let s = "Only one string".to_string();
+        let res = longest(&s, &s);
+        assert!(std::ptr::eq(res, &s));
+    }
+
+    // Pretty JSON is spread over several lines, compact JSON isn't
+    #[test]
+    fn data_to_json_pretty_and_compact() {
+        let data = Data {
+            name: "Kalle Balle",
+            email: "kalle@balle.se".to_string(),
+            phone: None,
+        };
+
+        let pretty = data_to_json(&data, true).unwrap();
+        assert!(pretty.contains('\n'));
+
+        let compact = data_to_json(&data, false).unwrap();
+        assert!(!compact.contains('\n'));
+        assert_eq!(
+            compact,
+            "{\"name\":\"Kalle Balle\",\"email\":\"kalle@balle.se\"}"
+        );
+    }
+
+    // by_len plugs straight into max_by, on a tie the later item wins
+    #[test]
+    fn by_len_with_max_by() {
+        let words = vec!["ab", "abcd", "xy", "wxyz"];
+        assert_eq!(words.iter().copied().max_by(by_len), Some("wxyz"));
+        assert_eq!(words.iter().copied().min_by(by_len), Some("ab"));
+        assert_eq!(words.into_iter().max_by(by_len), Some("wxyz"));
+
+        let empty: Vec<&str> = Vec::new();
+        assert_eq!(empty.into_iter().max_by(by_len), None);
+    }
+
+    // Within the bound we get the longest, exactly max is still ok
+    #[test]
+    fn longest_bounded_within() {
+        assert_eq!(longest_bounded("abc", "ab", 3).unwrap(), "abc");
+        assert_eq!(longest_bounded("ab", "cd", 10).unwrap(), "cd");
+    }
+
+    // Either string being too long is an Err, whichever one it is
+    #[test]
+    fn longest_bounded_over() {
+        assert_eq!(
+            longest_bounded("abcd", "ab", 3).unwrap_err(),
+            MyError::with_code("validation", "a is 4 bytes, max is 3")
+        );
+        assert_eq!(
+            longest_bounded("ab", "abcd", 3).unwrap_err(),
+            MyError::with_code("validation", "b is 4 bytes, max is 3")
+        );
+    }
+
+    // Three records to a JSON array and back, we get the same records in the same order
+    #[test]
+    fn data_vec_json_round_trip() {
+        let records = vec![
+            Data {
+                name: "Kalle Balle",
+                email: "kalle@balle.se".to_string(),
+                phone: None,
+            },
+            Data {
+                name: "Nisse Hult",
+                email: "nisse@hult.se".to_string(),
+                phone: Some("+46701234567".to_string()),
+            },
+            Data {
+                name: "Pelle Svanslös",
+                email: "pelle@uppsala.se".to_string(),
+                phone: None,
+            },
+        ];
+
+        let json = data_vec_to_json(&records).unwrap();
+        assert!(json.starts_with('['));
+        assert_eq!(data_vec_from_json(&json).unwrap(), records);
+
+        assert_eq!(data_vec_from_json("[]").unwrap(), Vec::new());
+    }
+
+    // "a_b-c_d" is longer in bytes, but without the separators "abcde" wins
+    #[test]
+    fn longest_ignoring_separators() {
+        assert!("a_b-c_d".len() > "abcde".len());
+        assert_eq!(longest_ignoring("a_b-c_d", "abcde", &['_', '-']), "abcde");
+
+        // Nothing ignored and it's just a char count
+        assert_eq!(longest_ignoring("a_b-c_d", "abcde", &[]), "a_b-c_d");
+    }
+
+    // The example from the doc comment, counting fields instead of bytes flips the winner
+    #[test]
+    fn longest_by_custom_len() {
+        let fields = |s: &str| s.split(',').count();
+        assert_eq!(longest_by("a,b,c", "abcdefg", fields), "a,b,c");
+        assert_eq!(longest_by("a,b,c", "abcdefg", str::len), "abcdefg");
+
+        // Equal lengths, b wins
+        assert_eq!(longest_by(&1, &2, |_| 0), &2);
+    }
+
+    // The same email (in any case) gives the same hash, another email a different one
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn email_hash_is_stable() {
+        let data = |email: &str| Data {
+            name: "Kalle Balle",
+            email: email.to_string(),
+            phone: None,
+        };
+
+        let hash = data("kalle@balle.se").email_hash();
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, data("kalle@balle.se").email_hash());
+        assert_eq!(hash, data("Kalle@Balle.SE").email_hash());
+        assert_ne!(hash, data("nisse@hult.se").email_hash());
+    }
+
+    // Compared with the naive max_by_key over pseudo random inputs, the answer has to be the
+    // same string (not just as long), which also checks that the last one wins on a tie
+    #[test]
+    fn longest_of_str_matches_naive() {
+        // A small xorshift, good enough for test data and we don't need a rand dependency
+        let mut state: u32 = 0x2545_f491;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+
+        assert_eq!(longest_of_str(&[]), None);
+
+        for _ in 0..200 {
+            let owned: Vec<String> = (0..next() % 50)
+                .map(|_| "x".repeat((next() % 20) as usize))
+                .collect();
+            let items: Vec<&str> = owned.iter().map(String::as_str).collect();
+
+            let naive = items.iter().copied().max_by_key(|s| s.len());
+            assert_eq!(
+                longest_of_str(&items).map(str::as_ptr),
+                naive.map(str::as_ptr)
+            );
+        }
+    }
+
+    // To MessagePack and back, with and without a phone number
+    #[cfg(feature = "rmp-serde")]
+    #[test]
+    fn data_msgpack_round_trip() {
+        let mut data = Data {
+            name: "Kalle Balle",
+            email: "kalle@balle.se".to_string(),
+            phone: None,
+        };
+        let bytes = data_to_msgpack(&data).unwrap();
+        assert_eq!(data_from_msgpack(&bytes).unwrap(), data);
+
+        data.phone = Some("+46701234567".to_string());
+        let bytes = data_to_msgpack(&data).unwrap();
+        assert_eq!(data_from_msgpack(&bytes).unwrap(), data);
+    }
+
+    // The same record takes fewer bytes as MessagePack than as JSON
+    #[cfg(feature = "rmp-serde")]
+    #[test]
+    fn data_msgpack_smaller_than_json() {
+        let data = Data {
+            name: "Kalle Balle",
+            email: "kalle@balle.se".to_string(),
+            phone: Some("+46701234567".to_string()),
+        };
+        let msgpack = data_to_msgpack(&data).unwrap();
+        let json = data_to_json(&data, false).unwrap();
+        assert!(msgpack.len() < json.len());
+    }
+
+    // Valid UTF-8 on both sides, we get the longest back as a &str
+    #[test]
+    fn longest_str_checked_valid() {
+        assert_eq!(
+            longest_str_checked(b"abc", "åäö".as_bytes()).unwrap(),
+            "åäö"
+        );
+        assert_eq!(longest_str_checked(b"abcd", b"ab").unwrap(), "abcd");
+    }
+
+    // 0xff is never valid UTF-8, it's an Err whichever side it's on
+    #[test]
+    fn longest_str_checked_invalid() {
+        let e = longest_str_checked(b"abc", &[b'a', 0xff]).unwrap_err();
+        assert_eq!(e.code(), "utf8");
+        assert!(longest_str_checked(&[0xff, 0xff, 0xff, 0xff], b"a").is_err());
+    }
+
+    // "ABC" is a duplicate of "abc", so the first spelling is the one we get back
+    #[test]
+    fn longest_unique_ci_collapses_case() {
+        let items: Vec<String> = vec!["abc".into(), "xy".into(), "ABC".into()];
+        assert!(std::ptr::eq(longest_unique_ci(&items).unwrap(), &items[0]));
+
+        // Without the duplicate the tie goes to the later one as usual
+        let items: Vec<String> = vec!["Abc".into(), "aBC".into(), "xyz".into()];
+        assert_eq!(longest_unique_ci(&items).unwrap(), "xyz");
+
+        assert_eq!(longest_unique_ci(&[]), None);
+    }
+
+    // The field list is complete and every name in it can be looked up with field()
+    #[test]
+    fn data_field_names() {
+        assert_eq!(Data::FIELDS, &["name", "email", "phone"]);
+        assert_eq!(Data::field_names(), Data::FIELDS);
+
+        let data = Data {
+            name: "Kalle Balle",
+            email: "kalle@balle.se".to_string(),
+            phone: Some("+46701234567".to_string()),
+        };
+        assert!(Data::FIELDS.iter().all(|key| data.field(key).is_some()));
+        assert_eq!(Data::FIELDS.join(","), "name,email,phone");
+    }
+
+    // The RLI and PDI around the Arabic name make a longer in chars, but they don't show,
+    // on screen b is one character longer
+    #[cfg(feature = "bidi")]
+    #[test]
+    fn longest_visual_skips_bidi_controls() {
+        let a = "Hej \u{2067}مرحبا\u{2069}";
+        let b = "Hej مرحبا!";
+        assert!(a.chars().count() > b.chars().count());
+        assert_eq!(longest_visual(a, b), b);
+
+        // A right-to-left mark doesn't show either
+        assert_eq!(longest_visual("abc\u{200F}", "abc"), "abc");
+        assert_eq!(longest_visual("abcd", "مرحبا"), "مرحبا");
+    }
+
+    // The same person written with two different cases is only kept once
+    #[test]
+    fn normalized_data_in_hash_set() {
+        let data = |email: &str| Data {
+            name: "Kalle Balle",
+            email: email.to_string(),
+            phone: None,
+        };
+
+        let mut set = std::collections::HashSet::new();
+        set.insert(NormalizedData(data("Kalle@Balle.SE")));
+        set.insert(NormalizedData(data("kalle@balle.se")));
+        assert_eq!(set.len(), 1);
+
+        set.insert(NormalizedData(data("nisse@hult.se")));
+        assert_eq!(set.len(), 2);
+    }
+
+    // We get the same allocation back, not a copy, and the loser's extra owner is dropped
+    #[test]
+    fn longest_arc_shares_the_string() {
+        use std::sync::Arc;
+
+        let a: Arc<str> = Arc::from("Kalle Balle");
+        let b: Arc<str> = Arc::from("Nisse");
+
+        let res = longest_arc(Arc::clone(&a), Arc::clone(&b));
+        assert_eq!(&*res, "Kalle Balle");
+        assert!(Arc::ptr_eq(&res, &a));
+        assert_eq!(Arc::strong_count(&a), 2);
+        assert_eq!(Arc::strong_count(&b), 1);
+
+        drop(res);
+        assert_eq!(Arc::strong_count(&a), 1);
+    }
+
+    // Three lines where the middle one is broken, we still get the records around it
+    #[test]
+    fn data_from_jsonl_with_malformed_line() {
+        let input = "{\"name\":\"Kalle Balle\",\"email\":\"kalle@balle.se\"}\n\
+                     {\"name\":\"Nisse\"\n\
+                     {\"name\":\"Pelle\",\"email\":\"pelle@uppsala.se\",\"phone\":\"+46701234567\"}\n";
+
+        let records: Vec<_> = data_from_jsonl(input.as_bytes()).collect();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].as_ref().unwrap().name, "Kalle Balle");
+        assert_eq!(records[1].as_ref().unwrap_err().code(), "json");
+        assert_eq!(
+            records[2].as_ref().unwrap().phone.as_deref(),
+            Some("+46701234567")
+        );
+    }
+
+    // a is longer, it comes first
+    #[test]
+    fn split_by_len_a_longer() {
+        let a = "Kalle Balle".to_string();
+        let b = "Nisse".to_string();
+        assert_eq!(split_by_len(&a, &b), (&a, &b));
+    }
+
+    // b is longer, it comes first
+    #[test]
+    fn split_by_len_b_longer() {
+        let a = "Nisse".to_string();
+        let b = "Kalle Balle".to_string();
+        assert_eq!(split_by_len(&a, &b), (&b, &a));
+    }
+
+    // Equally long, just like longest b counts as the longer one
+    #[test]
+    fn split_by_len_tie() {
+        let a = "abc".to_string();
+        let b = "xyz".to_string();
+        let (longer, shorter) = split_by_len(&a, &b);
+        assert!(std::ptr::eq(longer, &b));
+        assert!(std::ptr::eq(shorter, &a));
+    }
+
+    // The red "hej" is longer in bytes, but on screen "hello" has more characters
+    #[test]
+    fn longest_visible_strips_ansi() {
+        let styled = "\x1b[1;31mhej\x1b[0m";
+        assert!(styled.len() > "hello".len());
+        assert_eq!(longest_visible(styled, "hello"), "hello");
+
+        // Without any styling it's just a char count
+        assert_eq!(longest_visible("abcd", "abc"), "abcd");
+        // Equally long once the styling is gone, b wins
+        assert_eq!(longest_visible("\x1b[32mabc\x1b[0m", "xyz"), "xyz");
+    }
+
+    // Every name uppercased, in the same order as the records
+    #[test]
+    fn map_names_uppercase() {
+        let records = vec![
+            Data {
+                name: "Kalle Balle",
+                email: "kalle@balle.se".to_string(),
+                phone: None,
+            },
+            Data {
+                name: "Nisse",
+                email: "nisse@hult.se".to_string(),
+                phone: None,
+            },
+        ];
+
+        assert_eq!(
+            map_names(&records, str::to_uppercase),
+            vec!["KALLE BALLE", "NISSE"]
+        );
+        assert_eq!(map_names(&[], str::to_uppercase), Vec::<String>::new());
+    }
+
+    // The same answer materialized as different string types
+    #[test]
+    fn longest_into_string_and_box() {
+        let s: String = longest_into("Kalle Balle", "Nisse");
+        assert_eq!(s, "Kalle Balle");
+
+        let boxed: Box<str> = longest_into("Nisse", "Kalle Balle");
+        assert_eq!(&*boxed, "Kalle Balle");
+
+        let shared = longest_into::<std::sync::Arc<str>>("abc", "xyz");
+        assert_eq!(&*shared, "xyz");
+    }
+
+    // Same name, new email, only the email shows up in the diff
+    #[test]
+    fn data_diff_one_field() {
+        let a = Data {
+            name: "Kalle Balle",
+            email: "kalle@balle.se".to_string(),
+            phone: None,
+        };
+        let b = a.with_email("kalle@example.com");
+
+        assert_eq!(
+            data_diff(&a, &b),
+            vec!["email: kalle@balle.se -> kalle@example.com"]
+        );
+        assert!(data_diff(&a, &a).is_empty());
+    }
+
+    // A phone that was added is shown with "-" for the missing side
+    #[test]
+    fn data_diff_missing_phone() {
+        let a = Data {
+            name: "Kalle Balle",
+            email: "kalle@balle.se".to_string(),
+            phone: None,
+        };
+        let b = Data {
+            phone: Some("+46701234567".to_string()),
+            ..a.with_email("kalle@balle.se")
+        };
+        assert_eq!(data_diff(&a, &b), vec!["phone: - -> +46701234567"]);
+    }
+
+    // Below the cap it's just longest, above it both count as cap long and b wins the tie
+    #[test]
+    fn longest_capped_changes_winner() {
+        let a = "a".repeat(1000);
+        let b = "b".repeat(10);
+
+        assert_eq!(longest_capped(&a, &b, 100), a);
+        assert_eq!(longest_capped(&a, &b, 10), b);
+        assert_eq!(longest_capped(&a, &b, 5), b);
+        assert_eq!(longest_capped(&b, &a, 5), a);
+    }
+
+    // Newlines, tabs and other control characters are gone, the rest is kept as is
+    #[test]
+    fn data_sanitized_removes_control_chars() {
+        let data = Data {
+            name: "Kalle\nERROR\tBalle",
+            email: "kalle@balle.se\r".to_string(),
+            phone: Some("+4670\u{7}1234567".to_string()),
+        };
+
+        assert_eq!(
+            data.sanitized(),
+            OwnedData {
+                name: "KalleERRORBalle".to_string(),
+                email: "kalle@balle.se".to_string(),
+                phone: Some("+46701234567".to_string()),
+            }
+        );
+        assert_eq!(data.name, "Kalle\nERROR\tBalle");
+    }
+
+    // Nothing to look at, no index
+    #[test]
+    fn argmax_len_empty() {
+        let items: [&str; 0] = [];
+        assert_eq!(argmax_len(&items), None);
+    }
+
+    // A single item is the longest
+    #[test]
+    fn argmax_len_single() {
+        assert_eq!(argmax_len(&["only".to_string()]), Some(0));
+    }
+
+    // On a tie the lowest index wins
+    #[test]
+    fn argmax_len_tie() {
+        assert_eq!(argmax_len(&["ab", "abcd", "xy", "wxyz"]), Some(1));
+        assert_eq!(argmax_len(&["abc", "xyz"]), Some(0));
+        assert_eq!(argmax_len(&["a", "ab", "abc"]), Some(2));
+    }
+
+    // Bad input that couldn't be read is "parse", input that was read but isn't allowed is "validation"
+    #[test]
+    fn errors_have_codes() {
+        assert_eq!("Kalle".parse::<OwnedData>().unwrap_err().code(), "parse");
+        assert_eq!(data_from_vcard("FN:Kalle").unwrap_err().code(), "parse");
+        assert_eq!(try_longest("", "a").unwrap_err().code(), "validation");
+        assert_eq!(
+            NonEmptyString::try_new("").unwrap_err().code(),
+            "validation"
+        );
     }
 }
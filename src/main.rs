@@ -1,4 +1,9 @@
+// main doesn't use the showcase modules, their tests do. So in a plain build everything in
+// them looks unused and we turn the lint off there, in a test build it's on and it reports
+// whatever not even a test uses.
+#[cfg_attr(not(test), allow(dead_code))]
 mod lifetime_ownership;
+#[cfg_attr(not(test), allow(dead_code))]
 mod match_result;
 
 /// Before we start working on a project we need so get to know Rust
@@ -1,4 +1,10 @@
-use std::{fs::File, io::Read, num::ParseIntError};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{Read, Write},
+    num::{IntErrorKind, ParseFloatError, ParseIntError},
+};
 
 /// This is a simple struct that will represent our Error
 /// It contains a simple String and can be printed nicely
@@ -22,26 +28,327 @@ use std::{fs::File, io::Read, num::ParseIntError};
 /// let my_error_into: MyError = i.into();
 /// let my_error_from: MyError::from(i);
 /// ```
-#[derive(Debug)]
+///
+/// Next to the message we keep a short code saying what kind of error it is,
+/// eg "io" or "parse". Code can match on that, the message is for humans.
+///
+/// MyError can be (de)serialized with serde too, so tests can keep expected errors in
+/// JSON fixtures like {"msg":"boom"}. The code is a Cow, our own codes are &'static str
+/// and stay Borrowed, while one read from JSON has to be an Owned String.
+/// When the code is left out we get "other", just like with MyError::new.
+///
+/// For an io error we also keep its std::io::ErrorKind, so is_retryable can tell a timeout
+/// from a missing file. ErrorKind can't be serialized, so it's skipped and lost in JSON.
+///
+/// The severity says how bad it is, so we can sort out what to look at first.
+/// Left out of the JSON it's Error, better to take an unknown error too seriously.
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct MyError {
     msg: String,
+    #[serde(default = "MyError::default_code")]
+    code: Cow<'static, str>,
+    #[serde(default)]
+    severity: Severity,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    location: Option<Location>,
+    #[serde(skip)]
+    io_kind: Option<std::io::ErrorKind>,
+}
+
+/// Where in the input an error is, both start at 1 just like in an editor.
+/// Only errors that know it have one, eg a JSON config with a missing comma or a line
+/// parse_setting couldn't read. Our own line based parsers point at where the line's
+/// content starts, after any indentation.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// How bad an error is. Most are Error, a few are something we can recover from,
+/// eg a missing config file where we fall back on a default is only a Warning.
+/// In JSON they're written in lowercase, eg "warning".
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    #[default]
+    Error,
+}
+
+impl MyError {
+    /// For when there's no other error to convert from, we simply state what went wrong.
+    /// Taking impl Into<String> lets callers pass both a &str and a String.
+    /// The code will be "other" and the severity Error.
+    pub fn new(msg: impl Into<String>) -> Self {
+        MyError {
+            msg: msg.into(),
+            code: Self::default_code(),
+            severity: Severity::Error,
+            location: None,
+            io_kind: None,
+        }
+    }
+
+    /// Like new but with a code of our own, eg "parse". This is what the From impls and
+    /// the parsers below use, and lifetime_ownership too, so it's visible in the whole crate.
+    /// The other fields start out just like for new, the few errors
+    /// that know more (a severity, a location) fill that in afterwards.
+    pub(crate) fn with_code(code: &'static str, msg: impl Into<String>) -> Self {
+        MyError {
+            code: code.into(),
+            ..MyError::new(msg)
+        }
+    }
+
+    /// Notes where in the input the error is, for parsers that know the line they're on.
+    /// raw is the whole line, the column is where its content starts.
+    fn at_line(self, line: usize, raw: &str) -> Self {
+        let column = raw.chars().take_while(|c| c.is_whitespace()).count() + 1;
+        MyError {
+            location: Some(Location { line, column }),
+            ..self
+        }
+    }
+
+    fn default_code() -> Cow<'static, str> {
+        "other".into()
+    }
+
+    /// What kind of error this is, eg "io", "parse" or "json"
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn location(&self) -> Option<Location> {
+        self.location
+    }
+
+    /// Whether trying again might work. Only some io errors are like that: the call was
+    /// interrupted, would have blocked, or timed out. A missing file or a typo in the
+    /// config will still be there the next time, so everything else is false.
+    pub fn is_retryable(&self) -> bool {
+        use std::io::ErrorKind;
+
+        matches!(
+            self.io_kind,
+            Some(ErrorKind::Interrupted | ErrorKind::WouldBlock | ErrorKind::TimedOut)
+        )
+    }
+
+    /// For web services that want to send the error back as JSON,
+    /// this gives us {"error":"<the message>","code":"<the code>"}.
+    ///
+    /// We borrow our fields into a small struct that derives Serialize and let serde do the
+    /// escaping, building the JSON with format! would break on a message containing a quote.
+    pub fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        struct ErrorBody<'a> {
+            error: &'a str,
+            code: &'a str,
+        }
+
+        let body = ErrorBody {
+            error: &self.msg,
+            code: &self.code,
+        };
+        serde_json::to_string(&body).expect("a struct of two strings always serializes")
+    }
+}
+
+/// Display is what lets us print the error with {} and call to_string() on it.
+impl std::fmt::Display for MyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+/// With Debug and Display in place MyError can implement std::error::Error, there's
+/// nothing more to write. That makes it a "proper" error that other libraries understand.
+///
+/// One of those is anyhow (the "anyhow" feature), it converts any std::error::Error
+/// into an anyhow::Error, so '?' just works in a function returning anyhow::Result:
+/// ```rust ignore
+/// fn run() -> anyhow::Result<()> {
+///     let setting = get_conf_val()?;
+///     println!("{}", setting.n);
+///     Ok(())
+/// }
+/// ```
+/// And if we need our MyError back we can downcast to it.
+/// showcase is a binary so this example never runs, the test my_error_into_anyhow does.
+impl std::error::Error for MyError {}
+
+/// With the "miette" feature MyError is a miette::Diagnostic, so a CLI can render it with
+/// miette's fancy report: the message, a code like "showcase::parse" and a hint what to do.
+/// Every method on Diagnostic has a default, we only fill in the ones we have something for.
+/// Diagnostic::code has the same name as our own code(), a plain e.code() still calls ours,
+/// inherent methods win over trait methods.
+#[cfg(feature = "miette")]
+impl miette::Diagnostic for MyError {
+    fn code<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        Some(Box::new(format!("showcase::{}", self.code)))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        let help = match self.code() {
+            "io" => "check that the config file exists and that we're allowed to read it",
+            "parse" => "the config should hold a single integer, eg 42",
+            "json" => "the config should be JSON like {\"n\": 42}",
+            "env" => "set the environment variable the config refers to",
+            "args" => "pass the setting as --n=<integer>",
+            _ => return None,
+        };
+        Some(Box::new(help))
+    }
 }
 
 /// As mentioned here's the From implementation
 // to help us go from std::io::Error into MyError
 impl From<std::io::Error> for MyError {
     fn from(e: std::io::Error) -> Self {
-        MyError { msg: e.to_string() }
+        // A missing file is often something we can live with, eg by using a default
+        let severity = if e.kind() == std::io::ErrorKind::NotFound {
+            Severity::Warning
+        } else {
+            Severity::Error
+        };
+        MyError {
+            severity,
+            io_kind: Some(e.kind()),
+            ..MyError::with_code("io", e.to_string())
+        }
     }
 }
 
 /// Same thing as above but from ParseIntError to MyError
 impl From<ParseIntError> for MyError {
     fn from(e: ParseIntError) -> Self {
-        MyError { msg: e.to_string() }
+        MyError::with_code("parse", e.to_string())
+    }
+}
+
+/// And the same for ParseFloatError, used by parse_float_setting
+impl From<ParseFloatError> for MyError {
+    fn from(e: ParseFloatError) -> Self {
+        MyError::with_code("parse", e.to_string())
+    }
+}
+
+/// And from the error we get when bytes aren't valid UTF-8, eg from std::str::from_utf8
+impl From<std::str::Utf8Error> for MyError {
+    fn from(e: std::str::Utf8Error) -> Self {
+        MyError::with_code("utf8", e.to_string())
+    }
+}
+
+/// And from serde_json::Error, for when the config is written as JSON.
+/// serde_json knows where in the input it gave up, we keep that as our Location.
+/// An error that isn't about the input, eg failing to write, has line 0, so no Location.
+impl From<serde_json::Error> for MyError {
+    fn from(e: serde_json::Error) -> Self {
+        let location = (e.line() > 0).then(|| Location {
+            line: e.line(),
+            column: e.column(),
+        });
+        MyError {
+            location,
+            ..MyError::with_code("json", e.to_string())
+        }
+    }
+}
+
+/// And from reqwest::Error, used when we fetch the config over http.
+/// It only exists when the "reqwest" feature is turned on.
+#[cfg(feature = "reqwest")]
+impl From<reqwest::Error> for MyError {
+    fn from(e: reqwest::Error) -> Self {
+        MyError::with_code("http", e.to_string())
+    }
+}
+
+/// And from the MessagePack errors, used by data_to_msgpack and data_from_msgpack.
+/// Writing and reading have an error type each. Only with the "rmp-serde" feature.
+#[cfg(feature = "rmp-serde")]
+impl From<rmp_serde::encode::Error> for MyError {
+    fn from(e: rmp_serde::encode::Error) -> Self {
+        MyError::with_code("msgpack", e.to_string())
+    }
+}
+
+#[cfg(feature = "rmp-serde")]
+impl From<rmp_serde::decode::Error> for MyError {
+    fn from(e: rmp_serde::decode::Error) -> Self {
+        MyError::with_code("msgpack", e.to_string())
+    }
+}
+
+/// And from notify::Error, used by the config watchers. Only with the "notify" feature.
+/// Most of them are plain io errors underneath, eg the file we watch was removed. Those
+/// we hand on to From<std::io::Error>, so they keep their ErrorKind and a timeout is still
+/// retryable. The rest are about notify itself, eg running out of watches.
+#[cfg(feature = "notify")]
+impl From<notify::Error> for MyError {
+    fn from(e: notify::Error) -> Self {
+        match e.kind {
+            notify::ErrorKind::Io(io) => io.into(),
+            _ => MyError::with_code("io", e.to_string()),
+        }
+    }
+}
+
+/// MyError is a general error, it can hold anything that went wrong.
+/// Sometimes we want to be more specific, here's an enum with exactly the ways
+/// parsing a config value can fail. Code that cares can match on the variants,
+/// code that doesn't can turn it into a MyError with '?' thanks to From.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseSettingError {
+    /// There was nothing to parse
+    Empty,
+    /// Not a number at all, eg "abc", we keep the std error for its message
+    NotAnInteger(ParseIntError),
+    /// A number, but it doesn't fit in an i32
+    OutOfRange,
+}
+
+/// Sorts the std ParseIntError into our variants by looking at its kind
+impl From<ParseIntError> for ParseSettingError {
+    fn from(e: ParseIntError) -> Self {
+        match e.kind() {
+            IntErrorKind::Empty => ParseSettingError::Empty,
+            IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => ParseSettingError::OutOfRange,
+            _ => ParseSettingError::NotAnInteger(e),
+        }
+    }
+}
+
+impl std::fmt::Display for ParseSettingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseSettingError::Empty => write!(f, "no value found in config"),
+            ParseSettingError::NotAnInteger(e) => write!(f, "{}", e),
+            ParseSettingError::OutOfRange => write!(f, "value does not fit in an i32"),
+        }
+    }
+}
+
+/// Here we layer the specific error into the general one
+impl From<ParseSettingError> for MyError {
+    fn from(e: ParseSettingError) -> Self {
+        MyError::with_code("parse", e.to_string())
     }
 }
 
+/// Reads our default config file, "./num.txt". See get_conf_val_from for the details.
+pub fn get_conf_val() -> Result<Setting, MyError> {
+    get_conf_val_from("./num.txt")
+}
+
 /// Scenario: We're reading a configuration file.
 /// We outsource the reading of the file to a separate method that returns a std::io::Result<File>
 /// We use the "if let" syntax to check if the value is Ok and then we read the file.
@@ -62,8 +369,10 @@ impl From<ParseIntError> for MyError {
 /// Or through the if let syntax as we do here.
 /// We could solve this through .map(...).or_else(). also.
 /// Tip: Try to refactor this into match/.map
-pub fn get_conf_val() -> Result<Setting, MyError> {
-    let file = read_conf_file("./num.txt");
+///
+/// If the file isn't there we fall back on a default of 5.
+pub fn get_conf_val_from(path: &str) -> Result<Setting, MyError> {
+    let file = read_conf_file(path);
 
     if let Ok(mut f) = file {
         let mut content = String::new();
@@ -72,15 +381,144 @@ pub fn get_conf_val() -> Result<Setting, MyError> {
         f.read_to_string(&mut content)?;
 
         // I mean, we could just unwrap and Panic/die if the file content is bad
-        // But '?' or a match/if let/map_error might be a better choice
-        // tip: Change file content to something that's not an i32 and use ? here instead of unwrap
-        let n = content.parse::<i32>().unwrap();
-        Ok(Setting { n })
+        // But '?' or a match/if let/map_error might be a better choice.
+        // parse_setting hands us a MyError that tells us which line was bad.
+        // Before that we fill in any ${VAR} placeholders from the environment.
+        parse_setting(&expand_env(&content)?)
     } else {
         Ok(Setting { n: 5 })
     }
 }
 
+/// Like get_conf_val_from but without the default, if the file can't be read we get the Err.
+/// Everything that can fail is a '?', this is how short the happy path gets.
+pub fn get_conf_val_strict(path: &str) -> Result<Setting, MyError> {
+    let content = std::fs::read_to_string(path)?;
+    parse_setting(&expand_env(&content)?)
+}
+
+/// Delivering on the tip above, here's the .or_else() version.
+///
+/// We try the primary config and only if that fails, or_else calls our closure
+/// and we try the fallback instead. The error from the primary is ignored (the '_'),
+/// if the fallback fails too that's the error we return.
+/// Both use get_conf_val_strict, with the default of get_conf_val_from
+/// the primary would never fail and we'd never look at the fallback.
+pub fn get_conf_val_chain(primary: &str, fallback: &str) -> Result<Setting, MyError> {
+    get_conf_val_strict(primary).or_else(|_| get_conf_val_strict(fallback))
+}
+
+/// Replaces ${VAR} placeholders in the config with the value of the environment variable VAR,
+/// so a config can say ${PORT} and get whatever PORT is set to.
+///
+/// A variable that isn't set is an error, silently putting an empty string in its
+/// place would only give us a confusing parse error later on.
+pub fn expand_env(content: &str) -> Result<String, MyError> {
+    let mut expanded = String::with_capacity(content.len());
+    let mut rest = content;
+
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find('}')
+            .ok_or_else(|| MyError::with_code("parse", "unterminated ${ in config"))?;
+
+        let name = &after[..end];
+        let value = std::env::var(name)
+            .map_err(|e| MyError::with_code("env", format!("${{{}}}: {}", name, e)))?;
+        expanded.push_str(&value);
+        rest = &after[end + 1..];
+    }
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+/// Here we turn the content of the config file into a Setting.
+///
+/// The config may span multiple lines, we skip blank lines and read the value
+/// from the first line that has something on it. Anything after that is an error.
+/// When parsing fails we keep track of where it happened so the message
+/// says which line to look at, eg "line 3: invalid digit found in string".
+///
+/// Notice the use of map_err, we can't rely on From here since we want to add
+/// the line number to the message.
+///
+/// There's no unwrap and no slicing at byte indices in here, so whatever content
+/// we're given we get an Ok or an Err back, never a panic.
+/// The fuzz target in fuzz/ keeps us honest: 'cargo fuzz run parse_setting'
+pub fn parse_setting(content: &str) -> Result<Setting, MyError> {
+    let mut setting = None;
+
+    for (i, raw) in content.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let line_no = i + 1;
+        let parse_error = |msg: String| {
+            MyError::with_code("parse", format!("line {}: {}", line_no, msg)).at_line(line_no, raw)
+        };
+        if setting.is_some() {
+            return Err(parse_error("unexpected content after value".to_string()));
+        }
+
+        let value = parse_setting_value(line).map_err(|e| parse_error(e.to_string()))?;
+        setting = Some(value);
+    }
+
+    setting.ok_or_else(|| ParseSettingError::Empty.into())
+}
+
+/// Parses a single config value, eg "42", into a Setting.
+///
+/// Unlike parse_setting this returns the specific ParseSettingError, so the caller
+/// can match on exactly what went wrong. The '?' uses our From<ParseIntError>
+/// to sort the std error into one of our variants.
+pub fn parse_setting_value(value: &str) -> Result<Setting, ParseSettingError> {
+    let n = value.trim().parse::<i32>()?;
+    Ok(Setting { n })
+}
+
+/// The JSON flavour of parse_setting, here the content looks like { "n": 42 }.
+///
+/// This is where the two halves of the showcase meet, serde does the parsing for us
+/// and '?' turns a serde_json::Error into a MyError through From.
+/// Something like { "n": "oops" } is not an i32 and gives us an Err.
+pub fn parse_setting_json(content: &str) -> Result<Setting, MyError> {
+    let setting = serde_json::from_str(content)?;
+    Ok(setting)
+}
+
+/// Reads a JSON config file, eg "./num.json", and parses it with parse_setting_json.
+/// Unlike get_conf_val there's no default here, a missing file is an Err.
+pub fn get_conf_val_json(path: &str) -> Result<Setting, MyError> {
+    let content = std::fs::read_to_string(path)?;
+    parse_setting_json(&content)
+}
+
+/// Same scenario as get_conf_val, but now the config lives on a web server.
+///
+/// Run it with 'cargo test --features reqwest'.
+/// Both http:// and https:// work, the TLS part is done by rustls so there's no OpenSSL to install.
+/// Failing to connect turns into a MyError through From and '?',
+/// and so does any status other than 200 OK, since a 404 page is not a config.
+#[cfg(feature = "reqwest")]
+pub fn get_conf_val_url(url: &str) -> Result<Setting, MyError> {
+    let response = reqwest::blocking::get(url)?;
+    if response.status() != reqwest::StatusCode::OK {
+        return Err(MyError::with_code(
+            "http",
+            format!("unexpected status: {}", response.status()),
+        ));
+    }
+
+    let content = response.text()?;
+    parse_setting(&content)
+}
+
 /// We're simply reading the file here, we could be checking stuff like:
 /// - Is the file in correct format?
 /// - Is the file locked?
@@ -89,22 +527,1484 @@ fn read_conf_file(path: &str) -> std::io::Result<File> {
 }
 
 /// This is simply a carrier of settings data
+///
+/// Deriving the serde traits lets us store it as JSON too, eg { "n": 42 }
+///
+/// The comparison traits let us sort settings and compare them with == and <.
+/// The derived ones compare the fields one by one in the order they're declared,
+/// n is the only field, so that's simply comparing by n.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Setting {
     n: i32,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl Setting {
+    /// Instead of failing on a value that's out of range we can pull it back inside.
+    /// We take self by value and hand back a new Setting, the old one is moved in and consumed.
+    /// Note: just like i32::clamp this panics if min is greater than max.
+    pub fn clamped(self, min: i32, max: i32) -> Setting {
+        Setting {
+            n: self.n.clamp(min, max),
+        }
+    }
 
-    // Here's a simple test to see if we get our result back.
-    // here we could handle any Errors if we wanted to, but we just unwrap
-    // we've already tested that it is ok.
-    #[test]
-    fn read_conf_42() {
-        let res = get_conf_val();
-        assert!(&res.is_ok());
-        let e = res.unwrap();
-        assert_eq!(e.n, 42);
+    /// Writes the setting as a line for a .env file, eg to_env_line("N") gives "N=42".
+    /// The key is used as is, by convention env vars are upper case.
+    pub fn to_env_line(&self, key: &str) -> String {
+        format!("{}={}", key, self.n)
+    }
+
+    /// Encodes the setting as 4 bytes, least significant byte first (little endian).
+    /// No serde here, an i32 already knows how to turn itself into bytes.
+    pub fn to_le_bytes(&self) -> [u8; 4] {
+        self.n.to_le_bytes()
+    }
+
+    /// The way back from to_le_bytes. Any 4 bytes make a valid i32, so this can't fail
+    /// and we don't need a Result.
+    pub fn from_le_bytes(b: [u8; 4]) -> Setting {
+        Setting {
+            n: i32::from_le_bytes(b),
+        }
+    }
+
+    /// Shows the setting as a percentage, eg "42%".
+    /// A percentage outside of 0-100 makes no sense, so we show the nearest valid one
+    /// and note what the real value was, eg "100% (out of range: 150)".
+    pub fn as_percent_string(&self) -> String {
+        let shown = self.n.clamp(0, 100);
+        if shown == self.n {
+            format!("{}%", shown)
+        } else {
+            format!("{}% (out of range: {})", shown, self.n)
+        }
+    }
+
+    /// The setting as key/value strings, eg {"n": "42"}, for templates and log lines.
+    ///
+    /// We don't list the fields ourselves, we let serde do it: to_value gives us a JSON object
+    /// with one entry per field, and we turn every value into a string. So when Setting grows
+    /// a field the map gets the key too without touching this code.
+    pub fn to_map(&self) -> std::collections::HashMap<String, String> {
+        let value = serde_json::to_value(self).expect("a struct of numbers always serializes");
+        let serde_json::Value::Object(fields) = value else {
+            unreachable!("Setting is a struct, it serializes to an object");
+        };
+
+        fields
+            .into_iter()
+            .map(|(key, value)| match value {
+                serde_json::Value::String(s) => (key, s),
+                other => (key, other.to_string()),
+            })
+            .collect()
+    }
+}
+
+/// A value that goes stale after a while, eg a cached setting we want to read again now and then.
+///
+/// T is generic, Expiring doesn't care what it holds, only when it was loaded.
+/// We use Instant rather than SystemTime, Instant never goes backwards so
+/// changing the clock on the machine can't make a value expire early (or never).
+pub struct Expiring<T> {
+    value: T,
+    loaded_at: std::time::Instant,
+    ttl: std::time::Duration,
+}
+
+impl<T> Expiring<T> {
+    pub fn new(value: T, ttl: std::time::Duration) -> Self {
+        Expiring {
+            value,
+            loaded_at: std::time::Instant::now(),
+            ttl,
+        }
+    }
+
+    /// With a ttl of zero the value is expired right away.
+    pub fn is_expired(&self) -> bool {
+        self.loaded_at.elapsed() >= self.ttl
+    }
+
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+}
+
+/// Reads the config through a cache, the file is only read again when the cached setting expired.
+///
+/// The caller owns the cache, we just get to borrow it mutably. An empty cache (None)
+/// counts as expired. ttl is what a freshly loaded setting gets, so it only matters when we reload.
+/// Just like get_conf_val_from a missing file gives us the default of 5.
+pub fn get_conf_val_cached<'a>(
+    path: &str,
+    cache: &'a mut Option<Expiring<Setting>>,
+    ttl: std::time::Duration,
+) -> Result<&'a Setting, MyError> {
+    // A match returning cached.value() in one arm and filling the cache in the other looks
+    // nicer, but today's borrow checker thinks the first arm's borrow is still alive in the
+    // second. So we first make sure the cache is fresh, and borrow it once that's done.
+    if cache.as_ref().is_none_or(Expiring::is_expired) {
+        let setting = get_conf_val_from(path)?;
+        return Ok(cache.insert(Expiring::new(setting, ttl)).value());
+    }
+
+    match cache {
+        Some(cached) => Ok(cached.value()),
+        None => unreachable!("an empty cache counts as expired"),
+    }
+}
+
+/// Keeps track of the last few settings we've loaded, handy when auditing config reloads.
+///
+/// A VecDeque is a ring buffer, we can push to the back and pop from the front cheaply.
+/// Once we hold cap settings, pushing a new one evicts the oldest so we never grow
+/// past our bound.
+pub struct SettingHistory {
+    buf: std::collections::VecDeque<Setting>,
+    cap: usize,
+}
+
+impl SettingHistory {
+    /// The buffer starts out empty and grows as settings come in, with_capacity(cap)
+    /// would allocate room for all of them up front, even if cap is huge and we only ever see a few.
+    pub fn new(cap: usize) -> Self {
+        SettingHistory {
+            buf: std::collections::VecDeque::new(),
+            cap,
+        }
+    }
+
+    /// Adds a setting, evicting the oldest one if we're full.
+    /// With a cap of 0 nothing is ever kept.
+    pub fn push(&mut self, setting: Setting) {
+        if self.cap == 0 {
+            return;
+        }
+        if self.buf.len() == self.cap {
+            self.buf.pop_front();
+        }
+        self.buf.push_back(setting);
+    }
+
+    /// The most recently pushed setting, None if we have none
+    pub fn latest(&self) -> Option<&Setting> {
+        self.buf.back()
+    }
+
+    /// All the settings we remember, oldest first
+    pub fn all(&self) -> &std::collections::VecDeque<Setting> {
+        &self.buf
+    }
+}
+
+/// Loads many config files in parallel, one Result per path in the same order as paths.
+///
+/// rayon gives us par_iter, a drop in replacement for iter that spreads the work
+/// over a thread pool. Each file is loaded with get_conf_val_from, so a missing
+/// file still gets the default. Run it with 'cargo test --features rayon'.
+///
+/// For this to compile the results have to be sent back across threads,
+/// so both Setting and MyError must be Send. They only hold an i32 and a String,
+/// so the compiler works that out for us.
+#[cfg(feature = "rayon")]
+pub fn load_many(paths: &[&str]) -> Vec<Result<Setting, MyError>> {
+    use rayon::prelude::*;
+
+    paths
+        .par_iter()
+        .map(|path| get_conf_val_from(path))
+        .collect()
+}
+
+/// Writes a setting to a config file, in the same plain format get_conf_val reads.
+///
+/// If two processes write at the same time the file can end up with a mix of both,
+/// eg "1" written over "22" leaves "12". With the "fs2" feature we take an exclusive
+/// advisory lock on the file before touching it, so writers wait for each other.
+/// Advisory means it only works if everyone asks for the lock, it doesn't stop a plain read.
+///
+/// Notice that we don't truncate when opening, that would empty the file before
+/// we hold the lock. We truncate with set_len once the file is ours.
+/// The lock is released when file is dropped at the end of the function.
+pub fn write_conf(path: &str, setting: &Setting) -> Result<(), MyError> {
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+
+    #[cfg(feature = "fs2")]
+    fs2::FileExt::lock_exclusive(&file)?;
+
+    file.set_len(0)?;
+    file.write_all(setting.n.to_string().as_bytes())?;
+    Ok(())
+}
+
+/// Appends a line to an audit log every time a setting changes, one JSON object per line:
+/// ```text
+/// {"from":{"n":1},"to":{"n":2},"ts":1760000000}
+/// ```
+/// This is called JSON lines, every line can be parsed on its own and appending never
+/// has to read or rewrite what's already in the file. ts is seconds since 1970 (unix time).
+///
+/// Just like MyError::to_json we borrow into a small struct and let serde do the writing.
+/// Opening with append(true) makes every write go to the end of the file, and create(true)
+/// gives us a new file the first time.
+pub fn append_change_log(path: &str, old: &Setting, new: &Setting) -> Result<(), MyError> {
+    #[derive(Serialize)]
+    struct ChangeEvent<'a> {
+        from: &'a Setting,
+        to: &'a Setting,
+        ts: u64,
+    }
+
+    // Only a clock set before 1970 makes this fail, then 0 is as good a time as any
+    let ts = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    let mut line = serde_json::to_string(&ChangeEvent {
+        from: old,
+        to: new,
+        ts,
+    })?;
+    line.push('\n');
+
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)?;
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Parses a key/value config, one "key=value" per line, where the separator is up to the caller.
+/// Some files use "n=42", others "n: 42", by taking sep as a parameter one function handles both.
+///
+/// Blank lines are skipped and whitespace around keys and values is ignored.
+/// Just like parse_setting the errors tell us which line is wrong.
+pub fn parse_config_with_sep(content: &str, sep: char) -> Result<Setting, MyError> {
+    let mut setting = None;
+
+    for (i, raw) in content.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let line_no = i + 1;
+        let parse_error = |msg: String| {
+            MyError::with_code("parse", format!("line {}: {}", line_no, msg)).at_line(line_no, raw)
+        };
+
+        let (key, value) = line
+            .split_once(sep)
+            .ok_or_else(|| parse_error(format!("expected key{}value", sep)))?;
+        match key.trim() {
+            "n" if setting.is_some() => return Err(parse_error("duplicate key \"n\"".to_string())),
+            "n" => {
+                setting = Some(parse_setting_value(value).map_err(|e| parse_error(e.to_string()))?)
+            }
+            other => return Err(parse_error(format!("unknown key \"{}\"", other))),
+        }
+    }
+
+    setting.ok_or_else(|| ParseSettingError::Empty.into())
+}
+
+/// Layered config, eg a base file with defaults and an optional override on top.
+/// If there's an override it wins, otherwise we keep the base.
+///
+/// Setting only has the one field, so taking the whole override is the same as
+/// merging field by field. Should Setting grow more fields they'd have to become
+/// Options in the override, so we can tell "not set" apart from a value.
+pub fn merge(base: Setting, over: Option<Setting>) -> Setting {
+    over.unwrap_or(base)
+}
+
+/// A boundary where panics stop. Whatever happens inside f, we get a Result back.
+///
+/// catch_unwind catches the panic while the stack unwinds and hands us its payload,
+/// which is usually the &str or String given to panic!. We turn that into a MyError.
+/// The UnwindSafe bound makes us promise f doesn't leave shared state half updated.
+/// Note that this is not a replacement for Result, it's a last line of defence,
+/// and it does nothing if the program is built with panic = "abort".
+pub fn catch<T>(f: impl FnOnce() -> T + std::panic::UnwindSafe) -> Result<T, MyError> {
+    std::panic::catch_unwind(f).map_err(|payload| {
+        let msg = if let Some(s) = payload.downcast_ref::<&str>() {
+            s.to_string()
+        } else if let Some(s) = payload.downcast_ref::<String>() {
+            s.clone()
+        } else {
+            "unknown panic".to_string()
+        };
+        MyError::with_code("panic", format!("panicked: {}", msg))
+    })
+}
+
+/// Where resolve_setting_from found the setting
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SettingSource {
+    Args,
+    Env,
+    File,
+    Default,
+}
+
+/// One place to ask for the setting, it looks in the same places a CLI tool usually does:
+/// the command line (--n=42), the SHOWCASE_N environment variable, ./num.txt, and
+/// if none of them has it the default of 5. See resolve_setting_from for the details.
+pub fn resolve_setting() -> Result<Setting, MyError> {
+    let args: Vec<String> = std::env::args().collect();
+    let env = std::env::var("SHOWCASE_N").ok();
+    resolve_setting_from(&args, env.as_deref(), "./num.txt").map(|(setting, _)| setting)
+}
+
+/// Tries each source in order and the first one that has a value wins, we also get told
+/// which one that was. The sources are parameters, so a test can stub any of them.
+///
+/// A source that isn't there is skipped, one that is there but holds garbage is an Err.
+/// Falling through to the next source would hide the typo in "--n=4x" from the user.
+/// We reuse what we already have: parse_from_args, parse_setting_value and get_conf_val_strict.
+pub fn resolve_setting_from(
+    args: &[String],
+    env: Option<&str>,
+    path: &str,
+) -> Result<(Setting, SettingSource), MyError> {
+    if args.iter().any(|arg| arg.starts_with("--n=")) {
+        return Ok((parse_from_args(args)?, SettingSource::Args));
+    }
+
+    if let Some(value) = env {
+        let setting = parse_setting_value(value)
+            .map_err(|e| MyError::with_code("env", format!("SHOWCASE_N: {}", e)))?;
+        return Ok((setting, SettingSource::Env));
+    }
+
+    if std::path::Path::new(path).exists() {
+        return Ok((get_conf_val_strict(path)?, SettingSource::File));
+    }
+
+    Ok((Setting { n: 5 }, SettingSource::Default))
+}
+
+/// Picks the setting out of a URL query string, eg "?n=42&lang=sv" or "lang=sv&n=%2D5".
+///
+/// In a URL some characters have to be written as a '%' and two hex digits,
+/// "%2D" is a '-' and a '+' stands for a space. We decode keys and values before we look
+/// at them, so "n=%2D5" is -5. A leading '?' is fine, pairs without a '=' are skipped.
+/// Just like parse_from_args the last n wins if there's more than one.
+pub fn parse_from_query(query: &str) -> Result<Setting, MyError> {
+    let query_error = |msg: String| MyError::with_code("query", msg);
+
+    let mut value = None;
+    for pair in query.trim_start_matches('?').split('&') {
+        if let Some((key, v)) = pair.split_once('=') {
+            if percent_decode(key).map_err(query_error)? == "n" {
+                value = Some(percent_decode(v).map_err(query_error)?);
+            }
+        }
+    }
+
+    let value = value.ok_or_else(|| query_error("missing n=<int>".to_string()))?;
+    parse_setting_value(&value).map_err(|e| query_error(format!("n: {}", e)))
+}
+
+/// Turns "%2D5" into "-5". We decode into bytes first, a multi byte character like 'å'
+/// is written as several %XX in a row ("%C3%A5") and is only valid UTF-8 once they're together.
+fn percent_decode(s: &str) -> Result<String, String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+
+    while let Some((&b, tail)) = rest.split_first() {
+        rest = tail;
+        match b {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                // from_str_radix would take a sign too, "%+5" isn't an escape so we
+                // check that both are hex digits ourselves first
+                let hex = rest
+                    .get(..2)
+                    .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| format!("bad percent escape in {:?}", s))?;
+                bytes.push(hex);
+                rest = &rest[2..];
+            }
+            _ => bytes.push(b),
+        }
+    }
+
+    String::from_utf8(bytes).map_err(|_| format!("{:?} is not valid UTF-8 once decoded", s))
+}
+
+/// Picks the setting out of the command line, eg 'showcase --n=42'.
+/// Pass it std::env::args().collect::<Vec<_>>() or, in a test, a Vec of your own.
+///
+/// We don't need a whole argument parser for one flag, strip_prefix does the job.
+/// Like most command line tools, if the flag is given more than once the last one wins,
+/// that's why we search from the back with rev().
+pub fn parse_from_args(args: &[String]) -> Result<Setting, MyError> {
+    let value = args
+        .iter()
+        .rev()
+        .find_map(|arg| arg.strip_prefix("--n="))
+        .ok_or_else(|| MyError::with_code("args", "missing --n=<int>"))?;
+
+    parse_setting_value(value).map_err(|e| MyError::with_code("args", format!("--n: {}", e)))
+}
+
+/// A config we can reload cheaply, we only read and parse the file again when it has changed.
+///
+/// Asking the file system for the modification time (mtime) is a lot cheaper than reading and
+/// parsing the file, so we remember the mtime of the version we loaded and compare.
+pub struct ReloadableConfig {
+    path: String,
+    mtime: Option<std::time::SystemTime>,
+    current: Setting,
+}
+
+impl ReloadableConfig {
+    /// Loads the config for the first time, a missing file is an Err just like get_conf_val_strict
+    pub fn new(path: &str) -> Result<Self, MyError> {
+        let mut config = ReloadableConfig {
+            path: path.to_string(),
+            mtime: None,
+            current: Setting { n: 0 },
+        };
+        config.reload_if_changed()?;
+        Ok(config)
+    }
+
+    /// Reloads the file if its mtime is different from the one we loaded, returns whether it did.
+    /// We compare with != rather than >, a file restored from a backup can go back in time.
+    pub fn reload_if_changed(&mut self) -> Result<bool, MyError> {
+        let mtime = std::fs::metadata(&self.path)?.modified()?;
+        if self.mtime == Some(mtime) {
+            return Ok(false);
+        }
+
+        self.current = get_conf_val_strict(&self.path)?;
+        self.mtime = Some(mtime);
+        Ok(true)
+    }
+
+    pub fn current(&self) -> &Setting {
+        &self.current
+    }
+}
+
+/// ReloadableConfig has to be asked whether the file changed, ConfigWatcher tells us instead.
+/// The OS lets the notify crate know when the file is modified, we read it again and send
+/// the result over a channel. The receiving end only has to wait for messages:
+/// ```rust ignore
+/// let watcher = ConfigWatcher::new("./num.txt")?;
+/// for setting in watcher.receiver() {
+///     println!("{:?}", setting.map(|s| s.n));
+/// }
+/// ```
+/// showcase is a binary so this example never runs, the test config_watcher_sends_new_setting does.
+///
+/// A single save can fire more than one event, eg one when the file is emptied and one
+/// when the new content is written. So we can get an Err for a half written file
+/// followed by an Ok once the write is done, the receiver shouldn't give up on the first Err.
+///
+/// The watcher stops when ConfigWatcher is dropped, so keep it alive as long as you listen.
+#[cfg(feature = "notify")]
+pub struct ConfigWatcher {
+    _watcher: notify::RecommendedWatcher,
+    rx: std::sync::mpsc::Receiver<Result<Setting, MyError>>,
+}
+
+#[cfg(feature = "notify")]
+impl ConfigWatcher {
+    pub fn new(path: &str) -> Result<Self, MyError> {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let watcher = watch_conf(path, move |result| {
+            // If nobody is listening anymore there's nobody to tell, so we ignore the error
+            let _ = tx.send(result);
+        })?;
+
+        Ok(ConfigWatcher {
+            _watcher: watcher,
+            rx,
+        })
+    }
+
+    pub fn receiver(&self) -> &std::sync::mpsc::Receiver<Result<Setting, MyError>> {
+        &self.rx
+    }
+}
+
+/// The part ConfigWatcher and watch_conf_stream share: watch path and call send with the
+/// freshly read setting every time the file is modified. How the result reaches the
+/// listener, a std channel or an async one, is up to send.
+#[cfg(feature = "notify")]
+fn watch_conf(
+    path: &str,
+    send: impl Fn(Result<Setting, MyError>) + Send + 'static,
+) -> Result<notify::RecommendedWatcher, MyError> {
+    use notify::{EventKind, RecursiveMode, Watcher};
+
+    let conf_path = path.to_string();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let result = match event {
+            Ok(event) => match event.kind {
+                EventKind::Modify(_) | EventKind::Create(_) => get_conf_val_strict(&conf_path),
+                _ => return,
+            },
+            Err(e) => Err(e.into()),
+        };
+        send(result);
+    })?;
+    watcher.watch(std::path::Path::new(path), RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
+
+/// ConfigWatcher for async code, the new settings come as a Stream instead of over a
+/// std channel, so waiting for one doesn't block the thread: watcher.next().await.
+///
+/// notify calls us on a thread of its own, an unbounded tokio channel gets the settings
+/// from there into async land. unfold turns the receiving end into a Stream, the watcher
+/// rides along in its state so it lives as long as the stream does.
+/// If we can't start watching the stream yields that one Err and ends.
+/// Like with ConfigWatcher there can be an Err for a half written file before the Ok.
+/// Only with the "tokio" feature.
+#[cfg(feature = "tokio")]
+pub fn watch_conf_stream(path: String) -> impl futures::Stream<Item = Result<Setting, MyError>> {
+    use futures::StreamExt;
+
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    match watch_conf(&path, move |result| {
+        let _ = tx.send(result);
+    }) {
+        Ok(watcher) => futures::stream::unfold((watcher, rx), |(watcher, mut rx)| async move {
+            let result = rx.recv().await?;
+            Some((result, (watcher, rx)))
+        })
+        .left_stream(),
+        Err(e) => futures::stream::once(async { Err(e) }).right_stream(),
+    }
+}
+
+/// Validates a setting against rules kept in a file instead of in the code, eg rules.txt:
+/// ```text
+/// min=0
+/// max=100
+/// ```
+/// Both rules are optional, a rule that isn't in the file isn't checked.
+/// A broken rules file is an Err too, better to find out than to skip the validation.
+pub fn validate_from_rules(setting: &Setting, rules_path: &str) -> Result<(), MyError> {
+    let content = std::fs::read_to_string(rules_path)?;
+
+    for (i, raw) in content.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let rule_error = |msg: String| {
+            MyError::with_code("parse", format!("{} line {}: {}", rules_path, i + 1, msg))
+                .at_line(i + 1, raw)
+        };
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| rule_error("expected rule=value".to_string()))?;
+        let limit = value
+            .trim()
+            .parse::<i32>()
+            .map_err(|e| rule_error(e.to_string()))?;
+
+        let ok = match key.trim() {
+            "min" => setting.n >= limit,
+            "max" => setting.n <= limit,
+            other => return Err(rule_error(format!("unknown rule \"{}\"", other))),
+        };
+        if !ok {
+            return Err(MyError::with_code(
+                "validation",
+                format!("n = {} breaks the rule {}", setting.n, line),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks every field and reports every problem at once, instead of stopping at the first one
+/// like validate_from_rules. That's friendlier when a user fixes a config by hand, they get
+/// the whole list in one go rather than one error per attempt.
+///
+/// Each field gets its own check that pushes to errors, nothing returns early.
+/// Setting only has n for now (0-100, the same range as rules.txt and as_percent_string),
+/// so today the Vec holds at most one error. A new field is a new check below.
+pub fn validate_all_fields(setting: &Setting) -> Result<(), Vec<MyError>> {
+    let mut errors = Vec::new();
+
+    if !(0..=100).contains(&setting.n) {
+        errors.push(MyError::with_code(
+            "validation",
+            format!("n = {} is not within 0-100", setting.n),
+        ));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Like Setting, but for settings that can be fractional, eg a ratio of 0.75
+#[derive(Debug)]
+pub struct FloatSetting {
+    value: f64,
+}
+
+impl FloatSetting {
+    /// The field is private so nobody can put a NaN in after parse_float_setting checked it,
+    /// reading it is fine though
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+}
+
+/// Parses a fractional setting like "3.14".
+///
+/// Here's a pitfall: "nan", "inf" and "infinity" are all valid f64 to Rust's parser,
+/// so parse::<f64>() happily accepts them. A config value of NaN never compares equal
+/// to anything, not even itself, and infinity breaks any arithmetic it's part of.
+/// So once parsed we also check that the value is finite.
+pub fn parse_float_setting(content: &str) -> Result<FloatSetting, MyError> {
+    let value = content.trim().parse::<f64>()?;
+    if !value.is_finite() {
+        return Err(MyError::with_code(
+            "parse",
+            format!("{} is not a finite number", value),
+        ));
+    }
+    Ok(FloatSetting { value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes content to a file in the temp dir and returns the path.
+    // The process id keeps parallel runs of the tests apart.
+    fn temp_conf(name: &str, content: &str) -> String {
+        let path = std::env::temp_dir().join(format!("showcase_{}_{}", std::process::id(), name));
+        std::fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    // A tiny http server for the url tests, it answers one request with the given
+    // status line and body and then goes away. Returns the url to call.
+    #[cfg(feature = "reqwest")]
+    fn serve_once(status: &'static str, body: &'static str) -> String {
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf);
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        format!("http://{}/conf", addr)
+    }
+
+    // Here's a simple test to see if we get our result back.
+    // here we could handle any Errors if we wanted to, but we just unwrap
+    // we've already tested that it is ok.
+    #[test]
+    fn read_conf_42() {
+        let res = get_conf_val();
+        assert!(&res.is_ok());
+        let e = res.unwrap();
+        assert_eq!(e.n, 42);
+    }
+
+    // Blank lines around the value are fine
+    #[test]
+    fn parse_setting_multi_line() {
+        let res = parse_setting("\n\n  42  \n\n");
+        assert_eq!(res.unwrap().n, 42);
+    }
+
+    // The error message should point us to the line that is broken
+    #[test]
+    fn parse_setting_names_bad_line() {
+        let res = parse_setting("\n\nnot a number\n");
+        let e = res.unwrap_err();
+        assert_eq!(e.msg, "line 3: invalid digit found in string");
+    }
+
+    #[test]
+    fn parse_setting_names_extra_line() {
+        let res = parse_setting("42\n\n43\n");
+        let e = res.unwrap_err();
+        assert_eq!(e.msg, "line 3: unexpected content after value");
+    }
+
+    #[test]
+    fn parse_setting_empty() {
+        let res = parse_setting("\n  \n");
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn clamped_below_min() {
+        let s = Setting { n: -5 }.clamped(0, 100);
+        assert_eq!(s.n, 0);
+    }
+
+    #[test]
+    fn clamped_in_range() {
+        let s = Setting { n: 42 }.clamped(0, 100);
+        assert_eq!(s.n, 42);
+    }
+
+    #[test]
+    fn clamped_above_max() {
+        let s = Setting { n: 150 }.clamped(0, 100);
+        assert_eq!(s.n, 100);
+    }
+
+    #[cfg(feature = "reqwest")]
+    #[test]
+    fn get_conf_val_url_42() {
+        let url = serve_once("200 OK", "42");
+        let res = get_conf_val_url(&url);
+        assert_eq!(res.unwrap().n, 42);
+    }
+
+    #[cfg(feature = "reqwest")]
+    #[test]
+    fn get_conf_val_url_not_found() {
+        let url = serve_once("404 Not Found", "nope");
+        let e = get_conf_val_url(&url).unwrap_err();
+        assert_eq!(e.msg, "unexpected status: 404 Not Found");
+    }
+
+    // A quickcheck style test without the dependency, we generate a lot of random
+    // strings from a small xorshift generator and make sure parse_setting never panics.
+    // If we do get a value back, its digits should be in there somewhere.
+    #[test]
+    fn parse_setting_never_panics() {
+        let alphabet: Vec<char> = "0123456789 +-\n\r\tx.é😀".chars().collect();
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for _ in 0..10_000 {
+            let len = (next() % 16) as usize;
+            let content: String = (0..len)
+                .map(|_| alphabet[(next() % alphabet.len() as u64) as usize])
+                .collect();
+
+            if let Ok(setting) = parse_setting(&content) {
+                assert!(content.contains(&setting.n.unsigned_abs().to_string()));
+            }
+        }
+    }
+
+    // We push 5 settings into a history that holds 3, the two oldest should be gone
+    #[test]
+    fn setting_history_evicts_oldest() {
+        let mut history = SettingHistory::new(3);
+        for n in 1..=5 {
+            history.push(Setting { n });
+        }
+
+        let all: Vec<i32> = history.all().iter().map(|s| s.n).collect();
+        assert_eq!(all, vec![3, 4, 5]);
+        assert_eq!(history.latest().map(|s| s.n), Some(5));
+    }
+
+    #[test]
+    fn setting_history_empty() {
+        let mut history = SettingHistory::new(0);
+        history.push(Setting { n: 1 });
+        assert!(history.latest().is_none());
+    }
+
+    // num.json holds { "n": 42 }
+    #[test]
+    fn read_conf_json_42() {
+        let res = get_conf_val_json("./num.json");
+        assert_eq!(res.unwrap().n, 42);
+    }
+
+    // "oops" is not an i32, serde says no and we get it back as a MyError
+    #[test]
+    fn parse_setting_json_wrong_type() {
+        let res = parse_setting_json("{\"n\": \"oops\"}");
+        let e = res.unwrap_err();
+        assert!(e.msg.starts_with("invalid type: string \"oops\""));
+    }
+
+    #[test]
+    fn to_env_line_works() {
+        let s = Setting { n: 42 };
+        assert_eq!(s.to_env_line("PORT"), "PORT=42");
+    }
+
+    #[test]
+    fn parse_setting_error_empty() {
+        let e = parse_setting_value("  ").unwrap_err();
+        assert_eq!(e, ParseSettingError::Empty);
+        assert_eq!(MyError::from(e).msg, "no value found in config");
+    }
+
+    #[test]
+    fn parse_setting_error_not_an_integer() {
+        let e = parse_setting_value("abc").unwrap_err();
+        assert!(matches!(e, ParseSettingError::NotAnInteger(_)));
+        assert_eq!(MyError::from(e).msg, "invalid digit found in string");
+    }
+
+    #[test]
+    fn parse_setting_error_out_of_range() {
+        let e = parse_setting_value("99999999999").unwrap_err();
+        assert_eq!(e, ParseSettingError::OutOfRange);
+        assert_eq!(MyError::from(e).msg, "value does not fit in an i32");
+    }
+
+    // MyError has to cross threads for load_many, this only compiles if it's Send
+    #[test]
+    fn my_error_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<MyError>();
+        assert_send::<Setting>();
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn load_many_matches_sequential() {
+        let one = temp_conf("load_many_1.txt", "1");
+        let two = temp_conf("load_many_2.txt", "2");
+        let bad = temp_conf("load_many_bad.txt", "bad");
+        let paths = [one.as_str(), two.as_str(), bad.as_str(), "./num.txt"];
+
+        // Settings and errors aren't comparable, so we compare what's inside them
+        let summary = |res: &Result<Setting, MyError>| match res {
+            Ok(s) => Ok(s.n),
+            Err(e) => Err(e.msg.clone()),
+        };
+        let parallel: Vec<_> = load_many(&paths).iter().map(summary).collect();
+        let sequential: Vec<_> = paths
+            .iter()
+            .map(|path| get_conf_val_from(path))
+            .collect::<Vec<_>>()
+            .iter()
+            .map(summary)
+            .collect();
+
+        assert_eq!(parallel, sequential);
+        assert_eq!(parallel[0], Ok(1));
+        assert_eq!(parallel[3], Ok(42));
+        assert!(parallel[2].is_err());
+    }
+
+    #[test]
+    fn le_bytes_round_trip() {
+        for n in [0, 42, -1, i32::MIN, i32::MAX] {
+            let bytes = Setting { n }.to_le_bytes();
+            assert_eq!(Setting::from_le_bytes(bytes).n, n);
+        }
+    }
+
+    // Little endian puts the least significant byte first, 42 is 0x2a
+    #[test]
+    fn le_bytes_endianness() {
+        let bytes = Setting { n: 42 }.to_le_bytes();
+        assert_eq!(bytes, 42i32.to_le_bytes());
+        assert_eq!(bytes, [0x2a, 0, 0, 0]);
+    }
+
+    #[test]
+    fn my_error_to_json() {
+        let e = parse_setting("nope").unwrap_err();
+        assert_eq!(
+            e.to_json(),
+            "{\"error\":\"line 1: invalid digit found in string\",\"code\":\"parse\"}"
+        );
+    }
+
+    // serde takes care of escaping for us
+    #[test]
+    fn my_error_to_json_escapes() {
+        let e = MyError::new("a \"quoted\" word");
+        assert_eq!(
+            e.to_json(),
+            "{\"error\":\"a \\\"quoted\\\" word\",\"code\":\"other\"}"
+        );
+    }
+
+    #[test]
+    fn write_conf_round_trip() {
+        let path = temp_conf("write_conf.txt", "");
+        write_conf(&path, &Setting { n: 7 }).unwrap();
+        assert_eq!(get_conf_val_from(&path).unwrap().n, 7);
+    }
+
+    // Two threads hammer the same file with values of different length,
+    // with the lock the file always holds exactly one of them
+    #[cfg(feature = "fs2")]
+    #[test]
+    fn write_conf_concurrent() {
+        let path = temp_conf("write_conf_concurrent.txt", "");
+
+        let writers: Vec<_> = [1, 22]
+            .iter()
+            .map(|&n| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..200 {
+                        write_conf(&path, &Setting { n }).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let n = parse_setting(&content).unwrap().n;
+        assert!(n == 1 || n == 22);
+    }
+
+    #[test]
+    fn parse_config_with_equals() {
+        let res = parse_config_with_sep("\nn = 42\n", '=');
+        assert_eq!(res.unwrap().n, 42);
+    }
+
+    #[test]
+    fn parse_config_with_colon() {
+        let res = parse_config_with_sep("n: 42", ':');
+        assert_eq!(res.unwrap().n, 42);
+    }
+
+    // Using the wrong separator gives us a pointer to the line
+    #[test]
+    fn parse_config_with_wrong_sep() {
+        let e = parse_config_with_sep("n: 42", '=').unwrap_err();
+        assert_eq!(e.msg, "line 1: expected key=value");
+    }
+
+    #[test]
+    fn parse_config_with_unknown_key() {
+        let e = parse_config_with_sep("n=1\nm=2", '=').unwrap_err();
+        assert_eq!(e.msg, "line 2: unknown key \"m\"");
+    }
+
+    #[test]
+    fn expand_env_defined() {
+        std::env::set_var("SHOWCASE_TEST_PORT", "8080");
+        let res = expand_env("port ${SHOWCASE_TEST_PORT}!");
+        assert_eq!(res.unwrap(), "port 8080!");
+    }
+
+    #[test]
+    fn expand_env_undefined() {
+        let e = expand_env("${SHOWCASE_TEST_UNDEFINED}").unwrap_err();
+        assert_eq!(
+            e.msg,
+            "${SHOWCASE_TEST_UNDEFINED}: environment variable not found"
+        );
+        assert_eq!(e.code, "env");
+    }
+
+    // The whole chain, the file holds a placeholder and we get the value it points to
+    #[test]
+    fn get_conf_val_expands_env() {
+        std::env::set_var("SHOWCASE_TEST_N", "43");
+        let path = temp_conf("expand_env.txt", "${SHOWCASE_TEST_N}\n");
+        assert_eq!(get_conf_val_from(&path).unwrap().n, 43);
+    }
+
+    #[test]
+    fn get_conf_val_chain_uses_fallback() {
+        let res = get_conf_val_chain("./does_not_exist.txt", "./num.txt");
+        assert_eq!(res.unwrap().n, 42);
+    }
+
+    #[test]
+    fn get_conf_val_chain_prefers_primary() {
+        let primary = temp_conf("chain_primary.txt", "1");
+        let res = get_conf_val_chain(&primary, "./num.txt");
+        assert_eq!(res.unwrap().n, 1);
+    }
+
+    #[test]
+    fn get_conf_val_chain_both_missing() {
+        let res = get_conf_val_chain("./does_not_exist.txt", "./nor_does_this.txt");
+        assert_eq!(res.unwrap_err().code, "io");
+    }
+
+    #[test]
+    fn as_percent_string_42() {
+        assert_eq!(Setting { n: 42 }.as_percent_string(), "42%");
+    }
+
+    #[test]
+    fn as_percent_string_0() {
+        assert_eq!(Setting { n: 0 }.as_percent_string(), "0%");
+    }
+
+    #[test]
+    fn as_percent_string_out_of_range() {
+        assert_eq!(
+            Setting { n: 150 }.as_percent_string(),
+            "100% (out of range: 150)"
+        );
+    }
+
+    #[test]
+    fn merge_with_override() {
+        let merged = merge(Setting { n: 1 }, Some(Setting { n: 2 }));
+        assert_eq!(merged.n, 2);
+    }
+
+    #[test]
+    fn merge_without_override() {
+        let merged = merge(Setting { n: 1 }, None);
+        assert_eq!(merged.n, 1);
+    }
+
+    #[test]
+    fn catch_ok() {
+        assert_eq!(catch(|| 42).ok(), Some(42));
+    }
+
+    // The panic message will show up in the test output, but the test itself goes on
+    #[test]
+    fn catch_panic() {
+        let res = catch(|| -> i32 { panic!("oh no!") });
+        let e = res.unwrap_err();
+        assert_eq!(e.msg, "panicked: oh no!");
+        assert_eq!(e.code, "panic");
+    }
+
+    #[test]
+    fn parse_from_args_present() {
+        let args = vec!["showcase".to_string(), "--n=42".to_string()];
+        assert_eq!(parse_from_args(&args).unwrap().n, 42);
+    }
+
+    #[test]
+    fn parse_from_args_absent() {
+        let args = vec!["showcase".to_string(), "--verbose".to_string()];
+        let e = parse_from_args(&args).unwrap_err();
+        assert_eq!(e.msg, "missing --n=<int>");
+    }
+
+    #[test]
+    fn parse_from_args_malformed() {
+        let args = vec!["showcase".to_string(), "--n=lots".to_string()];
+        let e = parse_from_args(&args).unwrap_err();
+        assert_eq!(e.msg, "--n: invalid digit found in string");
+    }
+
+    #[test]
+    fn reloadable_config_reloads_on_change() {
+        let path = temp_conf("reloadable.txt", "1");
+        let mut config = ReloadableConfig::new(&path).unwrap();
+        assert_eq!(config.current().n, 1);
+        assert!(!config.reload_if_changed().unwrap());
+
+        // Some file systems only keep the mtime in coarse steps, so instead of hoping the
+        // clock moved on we set a later mtime on the file ourselves
+        std::fs::write(&path, "2").unwrap();
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(10);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+
+        assert!(config.reload_if_changed().unwrap());
+        assert_eq!(config.current().n, 2);
+        assert!(!config.reload_if_changed().unwrap());
+    }
+
+    // An expected error kept as JSON, the code is left out so it's "other"
+    #[test]
+    fn my_error_from_json_fixture() {
+        let expected: MyError = serde_json::from_str("{\"msg\":\"boom\"}").unwrap();
+        assert_eq!(expected, MyError::new("boom"));
+    }
+
+    #[test]
+    fn my_error_serde_round_trip() {
+        let e = parse_setting("nope").unwrap_err();
+        let json = serde_json::to_string(&e).unwrap();
+        assert_eq!(
+            json,
+            "{\"msg\":\"line 1: invalid digit found in string\",\"code\":\"parse\",\"severity\":\"error\",\"location\":{\"line\":1,\"column\":1}}"
+        );
+        assert_eq!(serde_json::from_str::<MyError>(&json).unwrap(), e);
+    }
+
+    // rules.txt says min=0 and max=100
+    #[test]
+    fn validate_from_rules_passes() {
+        assert!(validate_from_rules(&Setting { n: 42 }, "./rules.txt").is_ok());
+    }
+
+    #[test]
+    fn validate_from_rules_fails() {
+        let e = validate_from_rules(&Setting { n: 150 }, "./rules.txt")
+            .err()
+            .unwrap();
+        assert_eq!(e.msg, "n = 150 breaks the rule max=100");
+        assert_eq!(e.code, "validation");
+
+        assert!(validate_from_rules(&Setting { n: -1 }, "./rules.txt").is_err());
+    }
+
+    #[cfg(feature = "anyhow")]
+    #[test]
+    fn my_error_into_anyhow() {
+        fn load() -> anyhow::Result<Setting> {
+            let setting = parse_setting("nope")?;
+            Ok(setting)
+        }
+
+        let e = load().unwrap_err();
+        let my_error = e.downcast::<MyError>().unwrap();
+        assert_eq!(my_error.code, "parse");
+    }
+
+    #[test]
+    fn parse_float_setting_ok() {
+        // Comparing the text keeps clippy from telling us to use std::f64::consts::PI
+        let s = parse_float_setting("3.14").unwrap();
+        assert_eq!(s.value().to_string(), "3.14");
+    }
+
+    #[test]
+    fn parse_float_setting_nan() {
+        let e = parse_float_setting("nan").unwrap_err();
+        assert_eq!(e.msg, "NaN is not a finite number");
+    }
+
+    #[test]
+    fn parse_float_setting_inf() {
+        assert!(parse_float_setting("inf").is_err());
+        assert!(parse_float_setting("-infinity").is_err());
+    }
+
+    // We change the file and the watcher sends us the new setting, there may be an Err
+    // for the half written file first so we keep listening until we see the 2
+    #[cfg(feature = "notify")]
+    #[test]
+    fn config_watcher_sends_new_setting() {
+        use std::time::{Duration, Instant};
+
+        let path = temp_conf("watched.txt", "1");
+        let watcher = ConfigWatcher::new(&path).unwrap();
+        std::fs::write(&path, "2").unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut got = None;
+        while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+            match watcher.receiver().recv_timeout(left) {
+                Ok(Ok(setting)) if setting.n == 2 => {
+                    got = Some(setting.n);
+                    break;
+                }
+                Ok(_) => continue,
+                Err(_) => break,
+            }
+        }
+        assert_eq!(got, Some(2));
+    }
+
+    // With a tiny ttl the value expires almost right away, with a long one it doesn't
+    #[test]
+    fn expiring_is_expired() {
+        use std::time::Duration;
+
+        let short = Expiring::new(1, Duration::from_millis(1));
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(short.is_expired());
+
+        let long = Expiring::new(1, Duration::from_secs(3600));
+        assert!(!long.is_expired());
+        assert_eq!(*long.value(), 1);
+
+        assert!(Expiring::new(1, Duration::ZERO).is_expired());
+    }
+
+    // The file is only read again once the cached setting has expired
+    #[test]
+    fn get_conf_val_cached_reloads_when_expired() {
+        use std::time::Duration;
+
+        let path = temp_conf("cached.txt", "1");
+        let mut cache = None;
+
+        let hour = Duration::from_secs(3600);
+        assert_eq!(get_conf_val_cached(&path, &mut cache, hour).unwrap().n, 1);
+
+        std::fs::write(&path, "2").unwrap();
+        assert_eq!(get_conf_val_cached(&path, &mut cache, hour).unwrap().n, 1);
+
+        cache = Some(Expiring::new(Setting { n: 1 }, Duration::ZERO));
+        assert_eq!(get_conf_val_cached(&path, &mut cache, hour).unwrap().n, 2);
+    }
+
+    // Bad content is an Error, a file that isn't there only a Warning
+    #[test]
+    fn my_error_severity() {
+        let parse = parse_setting("nope").unwrap_err();
+        assert_eq!(parse.severity(), Severity::Error);
+        assert_eq!(
+            MyError::from("x".parse::<i32>().unwrap_err()).severity(),
+            Severity::Error
+        );
+
+        let missing = get_conf_val_strict("./does_not_exist.txt").unwrap_err();
+        assert_eq!(missing.severity(), Severity::Warning);
+
+        assert_eq!(MyError::new("boom").severity(), Severity::Error);
+    }
+
+    // Two changes give two lines, each one a JSON object of its own
+    #[test]
+    fn append_change_log_two_lines() {
+        let path = temp_conf("changes.jsonl", "");
+        append_change_log(&path, &Setting { n: 1 }, &Setting { n: 2 }).unwrap();
+        append_change_log(&path, &Setting { n: 2 }, &Setting { n: 3 }).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["from"]["n"], 1);
+        assert_eq!(lines[0]["to"]["n"], 2);
+        assert_eq!(lines[1]["to"]["n"], 3);
+        assert!(lines[1]["ts"].as_u64().unwrap() > 0);
+    }
+
+    // A parse error shows up as a diagnostic with our code and a hint
+    #[cfg(feature = "miette")]
+    #[test]
+    fn my_error_diagnostic_code() {
+        use miette::Diagnostic;
+
+        // MyError has its own code() too, so we have to say we mean the Diagnostic one
+        let e = parse_setting("nope").unwrap_err();
+        assert_eq!(Diagnostic::code(&e).unwrap().to_string(), "showcase::parse");
+        assert!(e.help().is_some());
+
+        assert!(MyError::new("boom").help().is_none());
+    }
+
+    // A plain query, with and without the '?' and among other parameters
+    #[test]
+    fn parse_from_query_simple() {
+        assert_eq!(parse_from_query("n=42").unwrap().n, 42);
+        assert_eq!(parse_from_query("?lang=sv&n=7&flag").unwrap().n, 7);
+        assert_eq!(parse_from_query("n=1&n=2").unwrap().n, 2);
+        assert_eq!(parse_from_query("lang=sv").unwrap_err().code(), "query");
+    }
+
+    // Percent-encoded keys and values are decoded before we parse them
+    #[test]
+    fn parse_from_query_percent_encoded() {
+        assert_eq!(parse_from_query("n=%2D5").unwrap().n, -5);
+        assert_eq!(parse_from_query("%6E=%34%32").unwrap().n, 42);
+        assert_eq!(parse_from_query("n=+42").unwrap().n, 42);
+
+        assert!(parse_from_query("n=%2").is_err());
+        assert!(parse_from_query("n=%zz").is_err());
+        assert!(parse_from_query("n=%+5").is_err());
+        assert!(parse_from_query("n=%ff").is_err());
+    }
+
+    // The missing comma is on line 3, serde_json tells us and we keep it
+    #[test]
+    fn json_error_keeps_location() {
+        let path = temp_conf("broken.json", "{\n  \"n\": 1\n  \"x\": 2\n}");
+        let e = get_conf_val_json(&path).unwrap_err();
+        assert_eq!(e.code(), "json");
+        assert_eq!(e.location(), Some(Location { line: 3, column: 3 }));
+
+        // A file that isn't there isn't about a place in the input, so there's none
+        let missing = get_conf_val_strict("./does_not_exist.txt").unwrap_err();
+        assert_eq!(missing.location(), None);
+    }
+
+    // Our own line based parsers know the line too, the column is where its content starts
+    #[test]
+    fn line_parsers_keep_location() {
+        let e = parse_setting("nope").unwrap_err();
+        assert_eq!(e.location(), Some(Location { line: 1, column: 1 }));
+
+        let e = parse_setting("42\n\n  43").unwrap_err();
+        assert_eq!(e.location(), Some(Location { line: 3, column: 3 }));
+
+        let e = parse_config_with_sep("n=1\nm=2", '=').unwrap_err();
+        assert_eq!(e.location(), Some(Location { line: 2, column: 1 }));
+
+        let rules = temp_conf("broken_rules.txt", "min=0\n max=ten");
+        let e = validate_from_rules(&Setting { n: 1 }, &rules)
+            .err()
+            .unwrap();
+        assert_eq!(e.location(), Some(Location { line: 2, column: 2 }));
+    }
+
+    // Every layer filled in, then one removed at a time, the highest one left always wins
+    #[test]
+    fn resolve_setting_precedence() {
+        let path = temp_conf("resolve.txt", "3");
+        let args = vec!["showcase".to_string(), "--n=1".to_string()];
+        let no_args = vec!["showcase".to_string()];
+        let missing = "./does_not_exist.txt";
+
+        let resolve = |args: &[String], env, path| {
+            let (setting, source) = resolve_setting_from(args, env, path).unwrap();
+            (setting.n, source)
+        };
+
+        assert_eq!(resolve(&args, Some("2"), &path), (1, SettingSource::Args));
+        assert_eq!(resolve(&no_args, Some("2"), &path), (2, SettingSource::Env));
+        assert_eq!(resolve(&no_args, None, &path), (3, SettingSource::File));
+        assert_eq!(
+            resolve(&no_args, None, missing),
+            (5, SettingSource::Default)
+        );
+    }
+
+    // A source that's there but broken is an Err, we don't fall through to the next one
+    #[test]
+    fn resolve_setting_broken_source() {
+        let no_args = vec!["showcase".to_string()];
+        let bad_args = vec!["--n=4x".to_string()];
+
+        let e = resolve_setting_from(&bad_args, Some("2"), "./num.txt")
+            .err()
+            .unwrap();
+        assert_eq!(e.code(), "args");
+        let e = resolve_setting_from(&no_args, Some("x"), "./num.txt")
+            .err()
+            .unwrap();
+        assert_eq!(e.code(), "env");
+    }
+
+    // A valid setting has no errors, an invalid one gets a list with the problem in it
+    #[test]
+    fn validate_all_fields_collects_errors() {
+        assert!(validate_all_fields(&Setting { n: 42 }).is_ok());
+
+        let errors = validate_all_fields(&Setting { n: 150 }).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code(), "validation");
+        assert_eq!(errors[0].to_string(), "n = 150 is not within 0-100");
+    }
+
+    // One entry per field, the value written as a string
+    #[test]
+    fn setting_to_map() {
+        let map = Setting { n: 42 }.to_map();
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.get("n").map(String::as_str), Some("42"));
+    }
+
+    // A timeout may go away if we try again, a value that isn't a number won't
+    #[test]
+    fn my_error_is_retryable() {
+        let timeout = std::io::Error::new(std::io::ErrorKind::TimedOut, "too slow");
+        assert!(MyError::from(timeout).is_retryable());
+
+        assert!(!parse_setting("nope").unwrap_err().is_retryable());
+        assert!(!get_conf_val_strict("./does_not_exist.txt")
+            .err()
+            .unwrap()
+            .is_retryable());
+    }
+
+    // Settings sort by n, and compare with == and < too
+    #[test]
+    fn setting_sorts_by_value() {
+        let mut settings: Vec<Setting> = [3, -1, 2].iter().map(|&n| Setting { n }).collect();
+        settings.sort();
+        let values: Vec<i32> = settings.iter().map(|s| s.n).collect();
+        assert_eq!(values, vec![-1, 2, 3]);
+
+        assert!(Setting { n: 1 } < Setting { n: 2 });
+        assert!(Setting { n: 1 } == Setting { n: 1 });
+        assert_eq!(settings.iter().max().map(|s| s.n), Some(3));
+    }
+
+    // The async cousin of config_watcher_sends_new_setting, we poll the stream until the 2
+    // shows up, possibly after an Err for the half written file
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn watch_conf_stream_yields_update() {
+        use futures::StreamExt;
+
+        let path = temp_conf("watched_async.txt", "1");
+        let stream = watch_conf_stream(path.clone());
+        futures::pin_mut!(stream);
+        std::fs::write(&path, "2").unwrap();
+
+        let got = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while let Some(result) = stream.next().await {
+                if let Ok(setting) = result {
+                    if setting.n == 2 {
+                        return Some(setting.n);
+                    }
+                }
+            }
+            None
+        })
+        .await;
+        assert_eq!(got, Ok(Some(2)));
+    }
+
+    // An io error inside a notify error keeps its kind, so a timeout is still retryable
+    #[cfg(feature = "notify")]
+    #[test]
+    fn notify_io_error_keeps_kind() {
+        let timeout = std::io::Error::new(std::io::ErrorKind::TimedOut, "too slow");
+        let e = MyError::from(notify::Error::io(timeout));
+        assert_eq!(e.code(), "io");
+        assert!(e.is_retryable());
+
+        let e = MyError::from(notify::Error::generic("no more watches"));
+        assert!(!e.is_retryable());
+    }
+
+    // The real sources: the test runner's args have no --n= and SHOWCASE_N isn't set,
+    // so we get the 42 in ./num.txt
+    #[test]
+    fn resolve_setting_from_num_txt() {
+        std::env::remove_var("SHOWCASE_N");
+        assert_eq!(resolve_setting().unwrap().n, 42);
     }
 }